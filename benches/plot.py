@@ -1,7 +1,14 @@
+import csv
+import json
+import os
+import sys
+
 import matplotlib
 import matplotlib.pyplot as plt
 import numpy as np
 
+SIZES = [250, 500, 1300]
+
 def plot_results(label_data, plot_title, x_label, y_label):
 	x = np.arange(len(label_data['labels']))  # the label locations
 	width = 0.15  # the width of the bars
@@ -66,7 +73,70 @@ def plot_framerate():
 
 	plot_results(label_data, plot_title, x_label, y_label)
 
+def criterion_mean(criterion_dir, group, function, size):
+	"""Mean time of one `cargo bench` result, in μs."""
+	path = os.path.join(criterion_dir, group, function, str(size), 'new', 'estimates.json')
+	with open(path) as f:
+		estimates = json.load(f)
+
+	# The key was capitalised in older criterion releases
+	mean = estimates.get('mean', estimates.get('Mean'))
+	return round(mean['point_estimate'] / 1000.0, 2)
+
+def criterion_label_data(group, left, right, criterion_dir='target/criterion'):
+	"""Builds the `label_data` of `plot_results` from two benchmarks of the same group."""
+	return dict(
+		labels=['{0}x{0}'.format(size) for size in SIZES],
+		left_label=left,
+		left_means=[criterion_mean(criterion_dir, group, left, size) for size in SIZES],
+		right_label=right,
+		right_means=[criterion_mean(criterion_dir, group, right, size) for size in SIZES],
+	)
+
+def plot_criterion(group, left, right):
+	label_data = criterion_label_data(group, left, right)
+	print(label_data)
+
+	plot_title = 'Time per iteration of the {} benchmark'.format(group)
+	x_label = 'Size of CA Grid'
+	y_label = 'Time (μs)'
+
+	plot_results(label_data, plot_title, x_label, y_label)
+
+def compare_label_data(path, column):
+	"""Builds the `label_data` of `plot_results` from the CSV of `--compare`, GPU on the left."""
+	means = {'gpu': {}, 'cpu': {}}
+	labels = []
+
+	with open(path) as f:
+		for row in csv.DictReader(f):
+			label = '{}x{}'.format(row['width'], row['height'])
+			if label not in labels:
+				labels.append(label)
+			means[row['backend']][label] = round(float(row[column]), 2)
+
+	return dict(
+		labels=labels,
+		left_label='GPU',
+		left_means=[means['gpu'].get(label, 0) for label in labels],
+		right_label='CPU',
+		right_means=[means['cpu'].get(label, 0) for label in labels],
+	)
+
+def plot_compare(path):
+	x_label = 'Size of CA Grid'
+
+	plot_results(compare_label_data(path, 'tick_time_us'), 'Time per tick', x_label, 'Time (μs)')
+	plot_results(compare_label_data(path, 'ticks_per_second'), 'Ticks Per Second', x_label, 'TPS')
+
 
 if __name__ == '__main__':
-	plot_fps()
-	plot_framerate()
\ No newline at end of file
+	# python3 benches/plot.py criterion <group> <left> <right>, after `cargo bench`
+	if len(sys.argv) == 5 and sys.argv[1] == 'criterion':
+		plot_criterion(*sys.argv[2:])
+	# python3 benches/plot.py compare compare.csv, after `cargo run -- --compare ...`
+	elif len(sys.argv) == 3 and sys.argv[1] == 'compare':
+		plot_compare(sys.argv[2])
+	else:
+		plot_fps()
+		plot_framerate()
\ No newline at end of file
@@ -1,11 +1,134 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
 
+use rand::prng::XorShiftRng;
+use rand::SeedableRng;
 
+use automata_sandbox::ascii::{Grid, Tile, FULL_MASS};
+use automata_sandbox::cave::{self, CaveSettings};
+use automata_sandbox::scenario::{Generator, Scenario, Shape};
+use automata_sandbox::world::World;
 
-fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("sum test", |b| b.iter(|| sum_test(black_box(20))));
+/// The grid sizes of the RenderDoc captures, see `benches/plot.py`
+const SIZES: [usize; 3] = [250, 500, 1300];
+
+const SCENES: [&str; 3] = ["empty", "full_tank", "cave_with_water"];
+
+/// Ticks per iteration of the `ticks` group
+const TICKS: usize = 10;
+
+const SEED: u64 = 42;
+
+/// Builds the starting grid of one of `SCENES`
+fn scene(name: &str, size: usize) -> Grid {
+    let last = size as i32 - 1;
+    let walls = vec![
+        (Tile::Block, Shape::Rect { x0: 0, y0: last, x1: last, y1: last }),
+        (Tile::Block, Shape::Rect { x0: 0, y0: 0, x1: 0, y1: last }),
+        (Tile::Block, Shape::Rect { x0: last, y0: 0, x1: last, y1: last }),
+    ];
+
+    let scenario = match name {
+        "empty" => Scenario { width: size, height: size, ..Scenario::default() },
+        "full_tank" => {
+            let mut shapes = walls;
+            shapes.push((Tile::Water(FULL_MASS), Shape::Rect { x0: 1, y0: 1, x1: last - 1, y1: last - 1 }));
+            Scenario { width: size, height: size, shapes, ..Scenario::default() }
+        }
+        "cave_with_water" => Scenario {
+            width: size,
+            height: size,
+            seed: Some(SEED),
+            generator: Generator::Cave,
+            ..Scenario::default()
+        },
+        _ => unreachable!(),
+    };
+
+    let mut grid = scenario.build_grid();
+
+    // Fill the open cells of the upper half of the cave, so the water has somewhere to fall
+    if name == "cave_with_water" {
+        for y in 1..size / 2 {
+            for x in 1..size - 1 {
+                if grid.get(x, y) == Tile::Empty {
+                    grid.set(x, y, Tile::Water(FULL_MASS));
+                }
+            }
+        }
+    }
+
+    grid
+}
+
+fn world(name: &str, size: usize) -> World {
+    scene(name, size).to_world().unwrap()
 }
 
+fn bench_tick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tick");
+    group.sample_size(10);
+
+    for &size in &SIZES {
+        for &name in &SCENES {
+            let mut world = world(name, size);
+            group.bench_function(BenchmarkId::new(name, size), |b| b.iter(|| world.tick()));
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_ticks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ticks");
+    group.sample_size(10);
+
+    for &size in &SIZES {
+        for &name in &SCENES {
+            group.bench_function(BenchmarkId::new(name, size), |b| {
+                b.iter_batched(
+                    || world(name, size),
+                    |mut world| {
+                        for _ in 0..TICKS {
+                            world.tick();
+                        }
+                        world
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+
+    for &size in &SIZES {
+        for &name in &SCENES {
+            let world = world(name, size);
+            let mut buff = vec![0u32; size * size];
+            group.bench_function(BenchmarkId::new(name, size), |b| b.iter(|| world.render(black_box(&mut buff))));
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_cave(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cave");
+    group.sample_size(10);
+
+    for &size in &SIZES {
+        let mut rng = XorShiftRng::seed_from_u64(SEED);
+        group.bench_function(BenchmarkId::new("generate", size), |b| {
+            b.iter(|| cave::generate(size, size, &CaveSettings::cpu(), &mut rng))
+        });
+    }
+
+    group.finish();
+}
 
-criterion_group!(benches, criterion_benchmark);
-criterion_main!(benches);
\ No newline at end of file
+criterion_group!(benches, bench_tick, bench_ticks, bench_render, bench_cave);
+criterion_main!(benches);
@@ -4,8 +4,20 @@ layout (location = 0) in vec2 st;
 uniform vec2 u_resolution;
 uniform float u_time;
 uniform float u_dt;
-uniform vec2 u_mouse;
+uniform vec2 u_mouse;// cell under the cursor, mapped by the same viewport as the painting
 uniform float u_brush_size;
+uniform vec2 u_view_origin;// lower left corner of the part of the field the camera shows, in cells
+uniform vec2 u_view_size;// size of that part, in cells
+uniform int u_view;// debug view, see views::View
+uniform vec2 u_view_range;// values at the ends of the legend
+uniform int u_smooth_surface;// 1 fills water cells under empty ones only up to their mass
+uniform float u_max_mass;
+
+#define VIEW_NORMAL 0
+#define VIEW_MASS 1
+#define VIEW_PRESSURE 2
+#define VIEW_TYPE 3
+#define VIEW_FLOW 4
 
 #define CELL_EMPTY 0
 #define CELL_BLOCK 1
@@ -22,6 +34,11 @@ layout(shared, binding = 0) readonly buffer OutputData {
     Cell curr_gen[];
 };
 
+// The generation before curr_gen, for the flow view
+layout(shared, binding = 1) readonly buffer PreviousData {
+    Cell prev_gen[];
+};
+
 layout(shared, binding = 2) buffer TmpData {
     float tmp_data[];
 };
@@ -38,6 +55,74 @@ float rand(vec2 co){
     return fract(sin(dot(co.xy, vec2(12.9898, 78.233))) * 43758.5453);
 }
 
+// Same ramps as views::heatmap and views::diverging
+vec3 heatmap(float t) {
+    const vec3 stops[5] = vec3[5](
+        vec3(0x00, 0x00, 0x00),
+        vec3(0x20, 0x20, 0xc0),
+        vec3(0x00, 0xc0, 0xc0),
+        vec3(0xe0, 0xe0, 0x00),
+        vec3(0xff, 0x20, 0x20)
+    );
+
+    float scaled = clamp(t, 0.0, 1.0) * 4.0;
+    int i = min(int(scaled), 3);
+
+    return mix(stops[i], stops[i + 1], scaled - float(i)) / 255.0;
+}
+
+vec3 diverging(float t) {
+    const vec3 gain = vec3(0x40, 0x80, 0xff) / 255.0;
+    const vec3 loss = vec3(0xff, 0x40, 0x40) / 255.0;
+
+    return t < 0.0 ? loss * min(-t, 1.0) : gain * min(t, 1.0);
+}
+
+vec3 typeColor(int cell_type) {
+    if (cell_type == CELL_EMPTY) {
+        return vec3(0.0);
+    } else if (cell_type == CELL_BLOCK) {
+        return vec3(0xcc, 0xae, 0x62) / 255.0;
+    } else if (cell_type == CELL_WATER) {
+        return vec3(0.0, 0.0, 1.0);
+    } else if (cell_type == CELL_ACID) {
+        return vec3(0.0, 1.0, 0.0);
+    } else if (cell_type == CELL_SAND) {
+        return vec3(1.0, 1.0, 0.0);
+    }
+
+    return vec3(1.0, 0.0, 0.0);
+}
+
+// Colour of a cell in one of the debug views
+vec3 debugColor(Cell cell, int index) {
+    if (u_view == VIEW_TYPE) {
+        return typeColor(cell.type);
+    } else if (cell.type == CELL_BLOCK) {
+        return vec3(0x60) / 255.0;
+    }
+
+    float value = u_view == VIEW_FLOW ? cell.mass - prev_gen[index].mass : cell.mass;
+    float t = clamp((value - u_view_range.x) / (u_view_range.y - u_view_range.x), 0.0, 1.0);
+
+    return u_view == VIEW_FLOW ? diverging(2.0 * t - 1.0) : heatmap(t);
+}
+
+// Part of the fragment covered by water in a cell at the surface, antialiased over a
+// pixel that is `pixel_height` cells high
+float surfaceCoverage(Cell cell, ivec2 xy, vec2 field_coord, float pixel_height) {
+    bool open_above = xy.y + 1 >= int(u_resolution.y) || curr_gen[xy.x + (xy.y + 1) * int(u_resolution.x)].type == CELL_EMPTY;
+    float fill = clamp(cell.mass / u_max_mass, 0.0, 1.0);
+
+    if (u_smooth_surface == 0 || !open_above) {
+        return 1.0;
+    }
+
+    // The water rests on the bottom of the cell, y grows upwards
+    float height = field_coord.y - float(xy.y);
+    return clamp((fill - height) / pixel_height + 0.5, 0.0, 1.0);
+}
+
 float distanceToSegment(vec2 a, vec2 b, vec2 p) {
     vec2 pa = p - a, ba = b - a;
     float h = clamp(dot(pa, ba)/dot(ba, ba), 0.0, 1.0);
@@ -45,8 +130,9 @@ float distanceToSegment(vec2 a, vec2 b, vec2 p) {
 }
 
 
+// Antialiased over `f` screen pixels, whatever the size of a cell on screen
 float smoothedge(float v, float f) {
-    return smoothstep(0.0, f / u_resolution.x, v);
+    return smoothstep(0.0, f * fwidth(v), v);
 }
 
 
@@ -55,14 +141,15 @@ float circle(vec2 p, float radius) {
 }
 
 float ring(vec2 p, float radius, float width) {
-    return abs(length(p) - radius * 0.5) - width;
+    return abs(length(p) - radius) - width;
 }
 
 void main() {
-    ivec2 xy = ivec2(int(st.x * u_resolution.x), int(st.y * u_resolution.y));
+    vec2 field_coord = u_view_origin + st * u_view_size;
+    ivec2 xy = clamp(ivec2(field_coord), ivec2(0), ivec2(u_resolution) - 1);
     int curr_coord = xy.x + xy.y * int(u_resolution.x);
-
-    vec2 mouse_coord = vec2(u_mouse.x / u_resolution.x, 1 - u_mouse.y / u_resolution.y);
+    // Outside of any branch, derivatives are undefined in non-uniform control flow
+    float pixel_height = fwidth(field_coord.y);
 
     Cell cell = curr_gen[curr_coord];
     int cell_type = cell.type;
@@ -70,7 +157,9 @@ void main() {
     //    vec2 st = st / u_resolution.st;
     vec3 color = vec3(0.0);
 
-    if (cell_type == 99) {
+    if (u_view != VIEW_NORMAL) {
+        color = debugColor(cell, curr_coord);
+    } else if (cell_type == 99) {
         FragColor = vec4(1.0, 0.0, 0.0, 1.0);
         return;
     } else if (cell_type == CELL_ACID) {
@@ -78,7 +167,9 @@ void main() {
     } else if (cell_type == CELL_SAND)  {
         color = vec3(1, 1, 0);
     } else if (cell_type == CELL_WATER) {
-        color += hsv2rgb(vec3(0.61, 1.0, mix(0.7, 1.0, cell.mass)));
+        vec3 air = hsv2rgb(vec3(0, 0.0, clamp(rand(xy), 0.0, 0.15)));
+        vec3 water = hsv2rgb(vec3(0.61, 1.0, mix(0.7, 1.0, cell.mass)));
+        color += mix(air, water, surfaceCoverage(cell, xy, field_coord, pixel_height));
     } else if (cell_type == CELL_BLOCK) {
         color +=hsv2rgb(vec3(0.075, 0.6, mix(rand(xy), 0.46, 0.77)));
     } else {
@@ -86,8 +177,8 @@ void main() {
     }
 
     // MOUSE RING
-    float rad = mix(0.03, 0.08, u_brush_size / 7);
-    float d = min(1.0, ring(st - mouse_coord, rad, 0.001));
+    // In cells, it encloses every cell within u_brush_size of the cell under the cursor
+    float d = min(1.0, ring(field_coord - (u_mouse + 0.5), u_brush_size + 0.5, 0.0));
     d = smoothedge(d, 1.1);
 
     color = mix(1-color, color, d);
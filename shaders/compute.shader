@@ -14,21 +14,39 @@ struct Cell {
     float mass;
 };
 
-const float MAX_MASS = 1.0;
-const float MIN_MASS = 0.005;
-const float MAX_COMPRESSION = 0.625;
-const float MIN_FLOW = 0.01;
-const float MAX_FLOW = 4.0;
-const float FLOW_SPEED = 1.0;
+struct Emitter {
+    float x;
+    float y;
+    float radius;
+    float rate;
+};
+
+// Flow constants, see Params::gpu() for their defaults
+uniform float u_max_mass;
+uniform float u_min_mass;
+uniform float u_max_compression;
+uniform float u_min_flow;
+uniform float u_max_flow;
+uniform float u_flow_speed;
+
+#define MAX_MASS u_max_mass
+#define MIN_MASS u_min_mass
+#define MAX_COMPRESSION u_max_compression
+#define MIN_FLOW u_min_flow
+#define MAX_FLOW u_max_flow
+#define FLOW_SPEED u_flow_speed
 
 uniform vec2 u_resolution;//  Canvas size (width,height)
 uniform float u_dt;
 uniform float u_time;// Time in seconds since load
 uniform float u_brush_size;
+uniform float u_brush_flow;// mass poured (positive) or drained (negative) per tick, 0 paints u_drawing_type
 uniform int u_drawing;
 uniform int u_drawing_type;
-uniform vec2 u_mouse;// mouse position in screen pixels
+uniform vec2 u_stroke_start;// cell under the cursor when the brush last painted
+uniform vec2 u_stroke_end;// cell under the cursor now
 uniform int u_rotation_signal;
+uniform int u_emitter_count;
 
 layout(shared, binding = 0) readonly buffer InputData {
     Cell curr_gen[];
@@ -42,10 +60,24 @@ layout(shared, binding = 2) buffer TmpData {
     float mass_buffer[];
 };
 
+// Sources (positive rate) and drains (negative rate) from the scenario
+layout(shared, binding = 3) readonly buffer EmitterData {
+    Emitter emitters[];
+};
+
 int toIndex(ivec2 pos) {
     return pos.x + pos.y * int(u_resolution.x);
 }
 
+// Cells outside of the field behave like blocks, so nothing is read or written past its edges
+Cell neighbour(ivec2 pos) {
+    if (pos.x < 0 || pos.y < 0 || pos.x >= int(u_resolution.x) || pos.y >= int(u_resolution.y)) {
+        return Cell(CELL_BLOCK, 0.0);
+    }
+
+    return curr_gen[toIndex(pos)];
+}
+
 float getVerticalFlowValue(float total_mass) {
     if (total_mass <= MAX_MASS) {
         return MAX_MASS;
@@ -56,6 +88,12 @@ float getVerticalFlowValue(float total_mass) {
     }
 }
 
+float distanceToSegment(vec2 p, vec2 a, vec2 b) {
+    vec2 ab = b - a;
+    float t = dot(ab, ab) > 0.0 ? clamp(dot(p - a, ab) / dot(ab, ab), 0.0, 1.0) : 0.0;
+    return distance(p, a + t * ab);
+}
+
 float rand(vec2 co){
     return fract(sin(dot(co.xy, vec2(12.9898, 78.233))) * 43758.5453);
 }
@@ -83,6 +121,12 @@ void rotateCanvas(ivec2 xy_curr, Cell curr) {
 
 void main() {
     ivec2 xy_curr = ivec2(gl_GlobalInvocationID.xy);
+
+    // The dispatch is rounded up to whole work groups
+    if (xy_curr.x >= int(u_resolution.x) || xy_curr.y >= int(u_resolution.y)) {
+        return;
+    }
+
     int xy = toIndex(xy_curr);
     int xy_above = toIndex(xy_curr + ivec2(0, 1));
     int xy_below = toIndex(xy_curr + ivec2(0, -1));
@@ -90,10 +134,10 @@ void main() {
     int xy_left = toIndex(xy_curr + ivec2(-1, 0));
 
     Cell curr = curr_gen[xy];
-    Cell above = curr_gen[xy_above];
-    Cell below = curr_gen[xy_below];
-    Cell right = curr_gen[xy_right];
-    Cell left = curr_gen[xy_left];
+    Cell above = neighbour(xy_curr + ivec2(0, 1));
+    Cell below = neighbour(xy_curr + ivec2(0, -1));
+    Cell right = neighbour(xy_curr + ivec2(1, 0));
+    Cell left = neighbour(xy_curr + ivec2(-1, 0));
 
     if (u_rotation_signal == 1) {
         rotateCanvas(xy_curr, curr);
@@ -101,28 +145,32 @@ void main() {
     }
 
     if (u_drawing == DRAWING_ON) {
-        Cell new_cell = Cell (
-        u_drawing_type,
-        0.0
-        );
-
-        if (u_drawing_type == CELL_WATER) {
-            new_cell.mass = 1.0 * MAX_MASS;
+        // Every cell paints itself if it lies on the stroke, so nothing is written outside of the field
+        float stroke_distance = distanceToSegment(vec2(xy_curr), u_stroke_start, u_stroke_end);
+
+        if (stroke_distance <= u_brush_size) {
+            if (u_brush_flow != 0.0) {
+                // A flow changes the mass of the open cells, like an emitter
+                if (curr.type != CELL_BLOCK) {
+                    mass_buffer[xy] = max(mass_buffer[xy] + u_brush_flow, 0.0);
+                }
+            } else if (u_drawing_type == CELL_EMPTY || curr.type == CELL_EMPTY) {
+                // The eraser paints empty cells over anything, the other elements only fill empty space
+                curr = Cell (
+                u_drawing_type,
+                u_drawing_type == CELL_WATER ? 1.0 * MAX_MASS : 0.0
+                );
+
+                mass_buffer[xy] = curr.mass;
+            }
         }
+    }
 
-        int mouseX = int(u_mouse.x);
-        int mouseY = int(u_resolution.y) - int(u_mouse.y);
-        int radius = int(u_brush_size);
-
-        for (int x = -radius; x < radius; x++) {
-            int height = int(sqrt(radius * radius - x * x));
-            for (int y = -height; y < height; y++) {
-                int idx = toIndex(ivec2(mouseX, mouseY) + ivec2(x, y));
-
-                if (curr_gen[idx].type == CELL_EMPTY) {
-                    next_gen[idx] = new_cell;
-                    mass_buffer[idx] = new_cell.mass;
-                }
+    if (curr.type != CELL_BLOCK) {
+        for (int i = 0; i < u_emitter_count; i++) {
+            Emitter e = emitters[i];
+            if (distance(vec2(xy_curr), vec2(e.x, e.y)) <= e.radius) {
+                mass_buffer[xy] = max(mass_buffer[xy] + e.rate, 0.0);
             }
         }
     }
@@ -182,15 +230,17 @@ void main() {
     }
 
     if (remaining_mass > 0) {
-        flow = remaining_mass - getVerticalFlowValue(remaining_mass + above.mass);
-        if (flow > MIN_FLOW) {
-            flow *= FLOW_SPEED;
+        if (above.type != CELL_BLOCK) {
+            flow = remaining_mass - getVerticalFlowValue(remaining_mass + above.mass);
+            if (flow > MIN_FLOW) {
+                flow *= FLOW_SPEED;
 
-            flow = clamp(flow, 0, remaining_mass);
+                flow = clamp(flow, 0, remaining_mass);
 
-            mass_buffer[xy] -= flow;
-            mass_buffer[xy_above] += flow;
-            remaining_mass -= flow;
+                mass_buffer[xy] -= flow;
+                mass_buffer[xy_above] += flow;
+                remaining_mass -= flow;
+            }
         }
     }
 
@@ -0,0 +1,29 @@
+#version 440 core
+layout (location = 0) in vec2 st;
+
+uniform vec2 u_framebuffer_size;
+uniform vec2 u_hud_origin;// top left corner of the image, in framebuffer pixels from the top left of the window
+uniform vec2 u_hud_size;// in HUD pixels
+uniform float u_hud_scale;// framebuffer pixels per HUD pixel
+
+// The HUD image as 0xRRGGBB pixels, top row first
+layout(shared, binding = 4) readonly buffer HudData {
+    uint hud_pixels[];
+};
+
+out vec4 FragColor;
+
+void main() {
+    // HUD pixels from the top left corner of the image
+    vec2 from_top_left = vec2(gl_FragCoord.x, u_framebuffer_size.y - gl_FragCoord.y) - u_hud_origin;
+    ivec2 xy = ivec2(floor(from_top_left / u_hud_scale));
+
+    if (xy.x < 0 || xy.y < 0 || xy.x >= int(u_hud_size.x) || xy.y >= int(u_hud_size.y)) {
+        discard;
+    }
+
+    uint pixel = hud_pixels[xy.x + xy.y * int(u_hud_size.x)];
+    vec3 color = vec3((pixel >> 16) & 0xffu, (pixel >> 8) & 0xffu, pixel & 0xffu) / 255.0;
+
+    FragColor = vec4(color, 1.0);
+}
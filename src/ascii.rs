@@ -0,0 +1,343 @@
+//! Plain-text grid format used for levels, test fixtures and bug reports.
+//!
+//! Every line is one row of the grid, read top to bottom:
+//!
+//! - `#` a solid block
+//! - `~` a cell full of water
+//! - `1`..`9` a cell partially filled with water, in tenths of a full cell
+//! - `%` acid (only understood by the GPU cell format)
+//! - `.` an empty cell
+//!
+//! Leading and trailing whitespace and blank lines are ignored, so grids can
+//! be written inline in raw string literals.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::params::Params;
+use crate::world::{self, World};
+use crate::{Cell, CellType};
+
+/// Mass of a cell drawn as `~`. Tiles count water in full cells, converting
+/// to a CPU world or a cell buffer scales it by the backend's `max_mass`.
+pub const FULL_MASS: f32 = 1.0;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Tile {
+    Empty,
+    Block,
+    Water(f32),
+    Acid,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// A backend independent grid. Rows are stored top to bottom.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<Tile>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize) -> Self {
+        Grid {
+            width,
+            height,
+            tiles: vec![Tile::Empty; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Tile {
+        self.tiles[x + y * self.width]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, tile: Tile) {
+        self.tiles[x + y * self.width] = tile;
+    }
+
+    pub fn from_world(world: &World) -> Self {
+        let mut grid = Grid::new(world.width(), world.height());
+
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                let tile = match world.blocks[x][y] {
+                    world::Cell::Ground => Tile::Block,
+                    _ if world.mass[x][y] > world.params().min_mass => {
+                        Tile::Water(world.mass[x][y] / world.params().max_mass * FULL_MASS)
+                    }
+                    _ => Tile::Empty,
+                };
+                grid.set(x, y, tile);
+            }
+        }
+
+        grid
+    }
+
+    pub fn to_world(&self) -> Result<World, ParseError> {
+        self.to_world_with(Params::cpu())
+    }
+
+    /// Builds a CPU world running with `params`, a full cell holds their `max_mass`
+    pub fn to_world_with(&self, params: Params) -> Result<World, ParseError> {
+        let mut world = World::new(self.width, self.height);
+        let max_mass = params.max_mass;
+        world.set_params(params);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                match self.get(x, y) {
+                    Tile::Empty => {}
+                    Tile::Block => world.blocks[x][y] = world::Cell::Ground,
+                    Tile::Water(mass) => world.add_mass(x, y, mass / FULL_MASS * max_mass),
+                    Tile::Acid => return Err(ParseError {
+                        line: y + 1,
+                        column: x + 1,
+                        message: "acid is not supported by the CPU world".to_string(),
+                    }),
+                }
+            }
+        }
+
+        Ok(world)
+    }
+
+    /// Reads a GPU cell buffer. The buffer's first row is the bottom of the field.
+    pub fn from_cells(width: usize, height: usize, cells: &[Cell]) -> Self {
+        let mut grid = Grid::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let cell = cells[x + (height - 1 - y) * width];
+                let tile = match cell.element_type {
+                    t if t == CellType::Block as i32 => Tile::Block,
+                    t if t == CellType::Acid as i32 => Tile::Acid,
+                    _ if cell.mass >= Params::gpu().min_mass => Tile::Water(cell.mass),
+                    _ => Tile::Empty,
+                };
+                grid.set(x, y, tile);
+            }
+        }
+
+        grid
+    }
+
+    pub fn to_cells(&self) -> Vec<Cell> {
+        self.to_cells_with(Params::gpu())
+    }
+
+    /// A cell buffer for the compute shader running with `params`, a full cell
+    /// holds their `max_mass`
+    pub fn to_cells_with(&self, params: Params) -> Vec<Cell> {
+        let mut cells = vec![Cell::default(); self.width * self.height];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (element_type, mass) = match self.get(x, y) {
+                    Tile::Empty => (CellType::Empty, 0.0),
+                    Tile::Block => (CellType::Block, 0.0),
+                    Tile::Water(mass) => (CellType::Water, mass / FULL_MASS * params.max_mass),
+                    Tile::Acid => (CellType::Acid, 0.0),
+                };
+                cells[x + (self.height - 1 - y) * self.width] = Cell {
+                    element_type: element_type as i32,
+                    mass,
+                };
+            }
+        }
+
+        cells
+    }
+}
+
+fn water_char(mass: f32) -> char {
+    // Any water left in a cell shows up as at least one tenth
+    let tenths = ((mass / FULL_MASS) * 10.0).round().max(1.0);
+
+    if tenths >= 10.0 {
+        '~'
+    } else {
+        std::char::from_digit(tenths as u32, 10).unwrap()
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.height {
+            let row: String = (0..self.width)
+                .map(|x| match self.get(x, y) {
+                    Tile::Empty => '.',
+                    Tile::Block => '#',
+                    Tile::Water(mass) => water_char(mass),
+                    Tile::Acid => '%',
+                })
+                .collect();
+
+            writeln!(f, "{}", row)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Grid {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut width = 0;
+        let mut tiles = Vec::new();
+        let mut height = 0;
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let row_width = line.chars().count();
+            if height == 0 {
+                width = row_width;
+            } else if row_width != width {
+                return Err(ParseError {
+                    line: line_idx + 1,
+                    column: row_width.min(width) + 1,
+                    message: format!("expected a row of {} cells, found {}", width, row_width),
+                });
+            }
+
+            for (column, c) in line.chars().enumerate() {
+                let tile = match c {
+                    '.' => Tile::Empty,
+                    '#' => Tile::Block,
+                    '~' => Tile::Water(FULL_MASS),
+                    '%' => Tile::Acid,
+                    '1'..='9' => Tile::Water(c.to_digit(10).unwrap() as f32 * FULL_MASS / 10.0),
+                    _ => return Err(ParseError {
+                        line: line_idx + 1,
+                        column: column + 1,
+                        message: format!("unknown cell character '{}'", c),
+                    }),
+                };
+                tiles.push(tile);
+            }
+
+            height += 1;
+        }
+
+        if height == 0 {
+            return Err(ParseError {
+                line: 1,
+                column: 1,
+                message: "the grid is empty".to_string(),
+            });
+        }
+
+        Ok(Grid { width, height, tiles })
+    }
+}
+
+/// Parses a text grid straight into a CPU world.
+pub fn parse_world(text: &str) -> Result<World, ParseError> {
+    text.parse::<Grid>()?.to_world()
+}
+
+/// Parses a text grid into a GPU cell buffer, returning its width and height
+/// alongside the cells.
+pub fn parse_cells(text: &str) -> Result<(usize, usize, Vec<Cell>), ParseError> {
+    let grid = text.parse::<Grid>()?;
+
+    Ok((grid.width, grid.height, grid.to_cells()))
+}
+
+/// Prints a GPU cell buffer as a text grid.
+pub fn format_cells(width: usize, height: usize, cells: &[Cell]) -> String {
+    Grid::from_cells(width, height, cells).to_string()
+}
+
+#[test]
+fn test_grid_round_trip() {
+    let text = "#....#\n#.~5.#\n######\n";
+    let grid = text.parse::<Grid>().unwrap();
+
+    assert_eq!(grid.width, 6);
+    assert_eq!(grid.height, 3);
+    assert_eq!(grid.get(2, 1), Tile::Water(FULL_MASS));
+    assert_eq!(grid.get(3, 1), Tile::Water(0.5));
+    assert_eq!(grid.to_string(), text);
+}
+
+#[test]
+fn test_grid_indentation_is_ignored() {
+    let grid = "
+        #~#
+        ###
+    ".parse::<Grid>().unwrap();
+
+    assert_eq!(grid.to_string(), "#~#\n###\n");
+}
+
+#[test]
+fn test_grid_rejects_ragged_rows() {
+    let err = "###\n##\n".parse::<Grid>().unwrap_err();
+
+    assert_eq!(err.line, 2);
+}
+
+#[test]
+fn test_grid_rejects_unknown_characters() {
+    let err = "#?#".parse::<Grid>().unwrap_err();
+
+    assert_eq!((err.line, err.column), (1, 2));
+}
+
+#[test]
+fn test_world_round_trip() {
+    let text = "#..#\n#~3#\n####\n";
+    let world = parse_world(text).unwrap();
+
+    assert_eq!(world.to_string(), text);
+}
+
+#[test]
+fn test_world_counts_water_in_full_cells() {
+    let world = parse_world("#~5#\n####\n").unwrap();
+    let max_mass = world.params().max_mass;
+
+    assert_eq!(world.mass(1, 0), max_mass);
+    assert_eq!(world.mass(2, 0), 0.5 * max_mass);
+    assert_eq!(Grid::from_world(&world).get(1, 0), Tile::Water(FULL_MASS));
+}
+
+#[test]
+fn test_world_rejects_acid() {
+    assert!(parse_world("#%#").is_err());
+}
+
+#[test]
+fn test_cells_are_stored_bottom_up() {
+    let (width, height, cells) = parse_cells("~.\n#%\n").unwrap();
+
+    assert_eq!((width, height), (2, 2));
+    assert_eq!(cells[0].element_type, CellType::Block as i32);
+    assert_eq!(cells[1].element_type, CellType::Acid as i32);
+    assert_eq!(cells[2].element_type, CellType::Water as i32);
+    assert_eq!(cells[2].mass, FULL_MASS);
+    assert_eq!(format_cells(width, height, &cells), "~.\n#%\n");
+}
@@ -0,0 +1,167 @@
+//! Pan and zoom for both renderers.
+//!
+//! The camera picks the part of the field that fills the view. Positions in the
+//! view are given as fractions of its width and height, so the same camera
+//! works for the minifb buffer and for the GL viewport. The axes follow the
+//! frontend: `y` grows downwards on the CPU and upwards on the GPU.
+
+pub const MAX_ZOOM: f32 = 64.0;
+/// Zoom factor of one step of the mouse wheel
+pub const ZOOM_STEP: f32 = 1.25;
+/// Part of the view the camera moves per key press
+pub const PAN_STEP: f32 = 0.1;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Camera {
+    field: (f32, f32),
+    /// Corner of the visible part of the field with the lowest coordinates, in cells
+    origin: (f32, f32),
+    /// 1 shows the whole field
+    zoom: f32,
+}
+
+impl Camera {
+    /// A camera showing the whole field
+    pub fn new(field_width: usize, field_height: usize) -> Self {
+        Camera {
+            field: (field_width as f32, field_height as f32),
+            origin: (0.0, 0.0),
+            zoom: 1.0,
+        }
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn origin(&self) -> (f32, f32) {
+        self.origin
+    }
+
+    /// Size of the visible part of the field, in cells
+    pub fn visible_size(&self) -> (f32, f32) {
+        (self.field.0 / self.zoom, self.field.1 / self.zoom)
+    }
+
+    /// Position in the field shown at `(x, y)` in fractions of the view
+    pub fn to_field(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (width, height) = self.visible_size();
+
+        (self.origin.0 + x * width, self.origin.1 + y * height)
+    }
+
+    /// The cell shown at `(x, y)` in fractions of the view
+    pub fn to_cell(&self, view: (f32, f32)) -> (i32, i32) {
+        let (x, y) = self.to_field(view);
+
+        (x.floor() as i32, y.floor() as i32)
+    }
+
+    /// Zooms in by `factor`, or out if it is below 1, keeping the cell under
+    /// `anchor` in place
+    pub fn zoom_at(&mut self, factor: f32, anchor: (f32, f32)) {
+        let fixed = self.to_field(anchor);
+
+        self.zoom = (self.zoom * factor).clamp(1.0, MAX_ZOOM);
+
+        let (width, height) = self.visible_size();
+        self.origin = (fixed.0 - anchor.0 * width, fixed.1 - anchor.1 * height);
+        self.clamp();
+    }
+
+    /// Moves the camera by `(dx, dy)` fractions of the view
+    pub fn pan(&mut self, (dx, dy): (f32, f32)) {
+        let (width, height) = self.visible_size();
+
+        self.origin = (self.origin.0 + dx * width, self.origin.1 + dy * height);
+        self.clamp();
+    }
+
+    pub fn reset(&mut self) {
+        self.origin = (0.0, 0.0);
+        self.zoom = 1.0;
+    }
+
+    /// Keeps the view inside of the field
+    fn clamp(&mut self) {
+        let (width, height) = self.visible_size();
+
+        self.origin = (
+            self.origin.0.clamp(0.0, self.field.0 - width),
+            self.origin.1.clamp(0.0, self.field.1 - height),
+        );
+    }
+
+    /// Fills `view`, a buffer `view_width` pixels wide, with `pixel(position, size)`
+    /// called for the field position at the centre of every pixel and the size of
+    /// a pixel, both in cells
+    pub fn render(&self, view: &mut [u32], view_width: usize, pixel: impl Fn((f32, f32), (f32, f32)) -> u32) {
+        let view_height = view.len() / view_width;
+        let (width, height) = self.visible_size();
+        let size = (width / view_width as f32, height / view_height as f32);
+
+        for y in 0..view_height {
+            for x in 0..view_width {
+                let position = self.to_field((
+                    (x as f32 + 0.5) / view_width as f32,
+                    (y as f32 + 0.5) / view_height as f32,
+                ));
+
+                view[x + y * view_width] = pixel(position, size);
+            }
+        }
+    }
+
+    /// Fills `view`, a buffer `view_width` pixels wide, with the visible part of
+    /// `frame`, a rendering of the whole field. Every pixel shows the cell at
+    /// its centre.
+    pub fn sample(&self, frame: &[u32], view: &mut [u32], view_width: usize) {
+        let (field_width, field_height) = (self.field.0 as usize, self.field.1 as usize);
+
+        self.render(view, view_width, |(x, y), _| {
+            let cell_x = (x.floor().max(0.0) as usize).min(field_width - 1);
+            let cell_y = (y.floor().max(0.0) as usize).min(field_height - 1);
+
+            frame[cell_x + cell_y * field_width]
+        });
+    }
+}
+
+#[test]
+fn test_zoom_keeps_the_anchor_in_place() {
+    let mut camera = Camera::new(100, 50);
+
+    camera.zoom_at(4.0, (0.25, 0.5));
+
+    assert_eq!(camera.visible_size(), (25.0, 12.5));
+    assert_eq!(camera.to_field((0.25, 0.5)), (25.0, 25.0));
+}
+
+#[test]
+fn test_camera_stays_inside_the_field() {
+    let mut camera = Camera::new(100, 100);
+
+    camera.zoom_at(2.0, (1.0, 1.0));
+    camera.pan((1.0, 1.0));
+    assert_eq!(camera.origin(), (50.0, 50.0));
+
+    camera.zoom_at(0.1, (0.5, 0.5));
+    assert_eq!((camera.zoom(), camera.origin()), (1.0, (0.0, 0.0)));
+}
+
+#[test]
+fn test_sample_without_zoom_copies_the_frame() {
+    let camera = Camera::new(3, 2);
+    let frame = vec![1, 2, 3, 4, 5, 6];
+    let mut view = vec![0; 6];
+
+    camera.sample(&frame, &mut view, 3);
+    assert_eq!(view, frame);
+
+    // Half the resolution picks the cells under the pixel centres
+    let camera = Camera::new(4, 2);
+    let frame = vec![1, 2, 3, 4, 5, 6, 7, 8];
+    let mut view = vec![0; 2];
+    camera.sample(&frame, &mut view, 2);
+    assert_eq!(view, vec![6, 8]);
+}
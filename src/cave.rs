@@ -0,0 +1,105 @@
+use rand::Rng;
+
+/// Rules for the cellular automaton that carves procedural caves
+#[derive(Copy, Clone, Debug)]
+pub struct CaveSettings {
+    pub chance_to_start_alive: f64,
+    pub death_limit: i32,
+    pub birth_limit: i32,
+    pub steps: usize,
+}
+
+impl CaveSettings {
+    /// The rules used by the CPU world, where alive cells become ground
+    pub fn cpu() -> Self {
+        CaveSettings {
+            chance_to_start_alive: 0.35,
+            death_limit: 3,
+            birth_limit: 4,
+            steps: 3,
+        }
+    }
+
+    /// The rules used by the GPU field, where alive cells stay open
+    pub fn gpu() -> Self {
+        CaveSettings {
+            chance_to_start_alive: 0.38,
+            death_limit: 3,
+            birth_limit: 3,
+            steps: 4,
+        }
+    }
+}
+
+impl Default for CaveSettings {
+    fn default() -> Self {
+        CaveSettings::cpu()
+    }
+}
+
+fn count_neighbours(map: &[Vec<bool>], x: usize, y: usize) -> i32 {
+    let width = map.len() as i32;
+    let height = map[0].len() as i32;
+    let mut count_n = 0;
+
+    for i in -1..=1 {
+        for j in -1..=1 {
+            if i == 0 && j == 0 {
+                continue;
+            }
+
+            let n_x = x as i32 + i;
+            let n_y = y as i32 + j;
+
+            // Everything beyond the edges counts as a neighbour
+            if n_x < 0 || n_y < 0 || n_x >= width || n_y >= height || map[n_x as usize][n_y as usize] {
+                count_n += 1;
+            }
+        }
+    }
+
+    count_n
+}
+
+fn do_cave_generation_step(old_map: &[Vec<bool>], settings: &CaveSettings) -> Vec<Vec<bool>> {
+    let width = old_map.len();
+    let height = old_map[0].len();
+    let mut new_map = vec![vec![false; height]; width];
+
+    for i in 0..width {
+        for j in 0..height {
+            let nbs = count_neighbours(old_map, i, j);
+            if old_map[i][j] {
+                new_map[i][j] = nbs >= settings.death_limit;
+            } else {
+                new_map[i][j] = nbs > settings.birth_limit;
+            }
+        }
+    }
+
+    new_map
+}
+
+fn initialize_cave<R: Rng>(width: usize, height: usize, settings: &CaveSettings, rng: &mut R) -> Vec<Vec<bool>> {
+    let mut cave_map = vec![vec![false; height]; width];
+
+    for column in cave_map.iter_mut() {
+        for cell in column.iter_mut() {
+            let chance: f64 = rng.gen();
+            *cell = chance < settings.chance_to_start_alive;
+        }
+    }
+
+    cave_map
+}
+
+/// Generates a cave map indexed as `map[x][y]`. What an alive cell means is up to the caller.
+pub fn generate<R: Rng>(width: usize, height: usize, settings: &CaveSettings, rng: &mut R) -> Vec<Vec<bool>> {
+    let mut cave_map = initialize_cave(width, height, settings, rng);
+
+    for _ in 0..settings.steps {
+        cave_map = do_cave_generation_step(&cave_map, settings);
+    }
+
+    cave_map
+}
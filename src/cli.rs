@@ -0,0 +1,264 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use automata_sandbox::record::{Format, RecordOptions};
+use automata_sandbox::scheduler;
+use automata_sandbox::tools;
+use automata_sandbox::Backend;
+
+pub const USAGE: &str = "\
+Usage: automata-sandbox [OPTIONS]
+
+Options:
+    --backend <cpu|gpu>       Simulation backend (default: gpu)
+    --size <WIDTHxHEIGHT>     Grid size (default: 300x300 on the cpu, 256x256 on the gpu)
+    --scale <N>               Window pixels per cell (default: 2)
+    --seed <N>                Seed for the procedural maps
+    --scenario <FILE>         Scenario file to start from
+    --load <FILE>             Saved text grid to start from
+    --rate <N>                Updates per second, 1 to 1000000 (default: unlimited on
+                              the cpu, 400 on the gpu)
+    --ticks-per-frame <N>     Simulation ticks between rendered frames, on the gpu
+                              per update (default: 1)
+    --max-lag <MS>            Longest backlog of updates the gpu catches up on after
+                              a slow frame, in milliseconds (default: 250)
+    --vsync                   Sync the gpu window's frames to the display
+    --ticks <N>               Stop the simulation after N ticks
+    --flow-rate <RATE>        Water the brush pours or drains per cell and tick, in
+                              full cells (default: 0.05)
+    --headless                Run without opening a window, the gpu backend runs
+                              the shader logic on the cpu
+    --output <DIR>            Directory for headless and comparison results (default: .)
+    --snapshot-every <N>      Save a headless snapshot every N ticks
+    --images <SCALE>          Save headless states as PNG images too, SCALE pixels per cell
+    --compare <SIZES>         Time both backends at every size, e.g. 250,500x300,1300,
+                              and save compare.csv in the output directory
+    --validate                Report how close every parameter preset comes to hydrostatics
+    --screenshots <DIR>       Directory where screenshots and recordings are saved (default: .)
+    --record <FILE>           Record the headless run to a .gif or .y4m file
+    --record-format <FORMAT>  Format of the clips recorded with F10, gif or y4m (default: gif)
+    --record-stride <N>       Record a frame every N ticks (default: 1)
+    --record-scale <N>        Pixels of a recording per rendered pixel (default: 1)
+    --record-frames <N>       Stop recording after N frames
+    --stats <FILE>            Write per tick statistics as CSV
+    -h, --help                Print this message
+";
+
+pub struct Options {
+    pub backend: Backend,
+    pub size: Option<(usize, usize)>,
+    pub scale: u32,
+    pub seed: Option<u64>,
+    pub scenario: Option<PathBuf>,
+    pub load: Option<PathBuf>,
+    pub update_rate: Option<f64>,
+    pub ticks_per_frame: u32,
+    pub max_lag: Option<Duration>,
+    pub vsync: bool,
+    pub ticks: Option<u64>,
+    pub flow_rate: f32,
+    pub headless: bool,
+    pub output_dir: PathBuf,
+    pub snapshot_every: Option<u64>,
+    pub images: Option<usize>,
+    pub compare: Option<Vec<(usize, usize)>>,
+    pub validate: bool,
+    pub screenshot_dir: PathBuf,
+    pub recording: Option<PathBuf>,
+    pub record_format: Format,
+    pub record: RecordOptions,
+    pub stats: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            backend: Backend::Gpu,
+            size: None,
+            scale: 2,
+            seed: None,
+            scenario: None,
+            load: None,
+            update_rate: None,
+            ticks_per_frame: 1,
+            max_lag: None,
+            vsync: false,
+            ticks: None,
+            flow_rate: tools::DEFAULT_FLOW_RATE,
+            headless: false,
+            output_dir: PathBuf::from("."),
+            snapshot_every: None,
+            images: None,
+            compare: None,
+            validate: false,
+            screenshot_dir: PathBuf::from("."),
+            recording: None,
+            record_format: Format::Gif,
+            record: RecordOptions::default(),
+            stats: None,
+            help: false,
+        }
+    }
+}
+
+fn parse_size(value: &str) -> Result<(usize, usize), String> {
+    let mut parts = value.splitn(2, 'x');
+
+    match (parts.next().map(str::parse), parts.next().map(str::parse)) {
+        (Some(Ok(width)), Some(Ok(height))) if width >= 3 && height >= 3 => Ok((width, height)),
+        _ => Err(format!("invalid size '{}', expected WIDTHxHEIGHT of at least 3x3", value)),
+    }
+}
+
+/// A comma separated list of sizes, where a single number is a square grid
+fn parse_sizes(value: &str) -> Result<Vec<(usize, usize)>, String> {
+    value
+        .split(',')
+        .map(|size| match size.parse::<usize>() {
+            Ok(side) => parse_size(&format!("{0}x{0}", side)),
+            Err(_) => parse_size(size),
+        })
+        .collect()
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+const VALUED_FLAGS: &[&str] = &[
+    "--backend", "--size", "--scale", "--seed", "--scenario", "--load", "--rate",
+    "--ticks-per-frame", "--max-lag", "--ticks", "--flow-rate", "--output", "--snapshot-every", "--images", "--compare",
+    "--screenshots", "--record", "--record-format", "--record-stride", "--record-scale", "--record-frames", "--stats",
+];
+
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "-h" | "--help" => options.help = true,
+            "--headless" => options.headless = true,
+            "--validate" => options.validate = true,
+            "--vsync" => options.vsync = true,
+            _ if !VALUED_FLAGS.contains(&flag.as_str()) => return Err(format!("unknown option {}", flag)),
+            _ => {
+                let value = args.next().ok_or_else(|| format!("missing value for {}", flag))?;
+
+                match flag.as_str() {
+                    "--backend" => options.backend = value.parse()?,
+                    "--size" => options.size = Some(parse_size(&value)?),
+                    "--scale" => options.scale = parse_value(&flag, &value)?,
+                    "--seed" => options.seed = Some(parse_value(&flag, &value)?),
+                    "--scenario" => options.scenario = Some(PathBuf::from(value)),
+                    "--load" => options.load = Some(PathBuf::from(value)),
+                    "--rate" => options.update_rate = Some(parse_value(&flag, &value)?),
+                    "--ticks-per-frame" => options.ticks_per_frame = parse_value(&flag, &value)?,
+                    "--max-lag" => options.max_lag = Some(Duration::from_millis(parse_value(&flag, &value)?)),
+                    "--ticks" => options.ticks = Some(parse_value(&flag, &value)?),
+                    "--flow-rate" => options.flow_rate = parse_value(&flag, &value)?,
+                    "--output" => options.output_dir = PathBuf::from(value),
+                    "--snapshot-every" => options.snapshot_every = Some(parse_value(&flag, &value)?),
+                    "--images" => options.images = Some(parse_value(&flag, &value)?),
+                    "--compare" => options.compare = Some(parse_sizes(&value)?),
+                    "--screenshots" => options.screenshot_dir = PathBuf::from(value),
+                    "--record" => options.recording = Some(PathBuf::from(value)),
+                    "--record-format" => options.record_format = value.parse()?,
+                    "--record-stride" => options.record.stride = parse_value(&flag, &value)?,
+                    "--record-scale" => options.record.scale = parse_value(&flag, &value)?,
+                    "--record-frames" => options.record.max_frames = Some(parse_value(&flag, &value)?),
+                    "--stats" => options.stats = Some(PathBuf::from(value)),
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+
+    if options.scale == 0 || options.ticks_per_frame == 0 {
+        return Err("--scale and --ticks-per-frame must be at least 1".to_string());
+    }
+
+    if options.snapshot_every == Some(0) || options.images == Some(0) {
+        return Err("--snapshot-every and --images must be at least 1".to_string());
+    }
+
+    if options.record.stride == 0 || options.record.scale == 0 || options.record.max_frames == Some(0) {
+        return Err("--record-stride, --record-scale and --record-frames must be at least 1".to_string());
+    }
+
+    if let Some(path) = &options.recording {
+        if !options.headless {
+            return Err("--record needs --headless, windows record with F10".to_string());
+        }
+        Format::from_path(path)?;
+    }
+
+    if options.update_rate.is_some_and(|rate| !(scheduler::MIN_RATE..=scheduler::MAX_RATE).contains(&rate)) {
+        return Err(format!(
+            "--rate must be between {} and {} updates per second",
+            scheduler::MIN_RATE, scheduler::MAX_RATE,
+        ));
+    }
+
+    if options.max_lag == Some(Duration::ZERO) {
+        return Err("--max-lag must be positive".to_string());
+    }
+
+    if !(tools::MIN_FLOW_RATE..=tools::MAX_FLOW_RATE).contains(&options.flow_rate) {
+        return Err(format!(
+            "--flow-rate must be between {} and {} full cells",
+            tools::MIN_FLOW_RATE, tools::MAX_FLOW_RATE,
+        ));
+    }
+
+    if options.scenario.is_some() && options.load.is_some() {
+        return Err("--scenario and --load cannot be used together".to_string());
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+    line.split_whitespace().map(String::from)
+}
+
+#[test]
+fn test_parse_options() {
+    let options = parse(args("--backend cpu --size 128x64 --ticks 10 --headless --stats out.csv --vsync --max-lag 100")).unwrap();
+
+    assert_eq!(options.backend, Backend::Cpu);
+    assert_eq!(options.size, Some((128, 64)));
+    assert_eq!(options.ticks, Some(10));
+    assert!(options.headless);
+    assert_eq!(options.stats, Some(PathBuf::from("out.csv")));
+    assert!(options.vsync);
+    assert_eq!(options.max_lag, Some(Duration::from_millis(100)));
+}
+
+#[test]
+fn test_parse_rejects_bad_options() {
+    assert!(parse(args("--backend vulkan")).is_err());
+    assert!(parse(args("--size 64")).is_err());
+    assert!(parse(args("--scale")).is_err());
+    assert!(parse(args("--frobnicate 1")).is_err());
+    assert!(parse(args("--scenario a --load b")).is_err());
+    assert!(parse(args("--compare 250,2")).is_err());
+    assert!(parse(args("--flow-rate 0")).is_err());
+    assert!(parse(args("--images 0")).is_err());
+    assert!(parse(args("--headless --record clip.mp4")).is_err());
+    assert!(parse(args("--record clip.gif")).is_err());
+    assert!(parse(args("--record-stride 0")).is_err());
+    assert!(parse(args("--max-lag 0")).is_err());
+    assert!(parse(args("--rate nan")).is_err());
+    assert!(parse(args("--rate inf")).is_err());
+    assert!(parse(args("--rate 1e-20")).is_err());
+    assert!(parse(args("--rate 1e10")).is_err());
+}
+
+#[test]
+fn test_parse_compare_sizes() {
+    let options = parse(args("--compare 250,500x300")).unwrap();
+
+    assert_eq!(options.compare, Some(vec![(250, 250), (500, 300)]));
+}
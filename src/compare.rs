@@ -0,0 +1,140 @@
+//! Runs the same scenario on both backends and records how long a tick takes.
+//!
+//! The CPU side is timed with the wall clock around `World::tick`, the GPU side
+//! with timer queries around every compute dispatch, see `measure_gpu`.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::ascii::{Tile, FULL_MASS};
+use crate::scenario::{Generator, Scenario, Shape};
+use crate::Backend;
+
+/// Seed of the default scene, so every size gets the same kind of cave
+const SEED: u64 = 42;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Measurement {
+    pub backend: Backend,
+    pub width: usize,
+    pub height: usize,
+    pub ticks: u64,
+    /// Time spent in the ticks themselves, without any rendering
+    pub total_time: Duration,
+}
+
+impl Measurement {
+    pub fn tick_time(&self) -> Duration {
+        if self.ticks == 0 {
+            return Duration::ZERO;
+        }
+
+        self.total_time.div_f64(self.ticks as f64)
+    }
+
+    pub fn ticks_per_second(&self) -> f64 {
+        if self.ticks == 0 {
+            return 0.0;
+        }
+
+        self.ticks as f64 / self.total_time.as_secs_f64()
+    }
+
+    /// Cell updates per second
+    pub fn throughput(&self) -> f64 {
+        self.ticks_per_second() * (self.width * self.height) as f64
+    }
+}
+
+/// A cave with its upper half flooded, used when no scenario is given.
+/// The outer ring stays dry, `World::tick` drains any water there. Full cells
+/// hold each backend's `max_mass`, so both simulate the same volume.
+pub fn default_scenario(width: usize, height: usize) -> Scenario {
+    let flood = Shape::Rect {
+        x0: 1,
+        y0: 1,
+        x1: width as i32 - 2,
+        y1: height as i32 / 2,
+    };
+
+    Scenario {
+        width,
+        height,
+        seed: Some(SEED),
+        generator: Generator::Cave,
+        shapes: vec![(Tile::Water(FULL_MASS), flood)],
+        ..Scenario::default()
+    }
+}
+
+/// `scenario` resized to the given grid size, shapes that no longer fit are clipped
+pub fn resize(scenario: &Scenario, width: usize, height: usize) -> Scenario {
+    Scenario {
+        width,
+        height,
+        ..scenario.clone()
+    }
+}
+
+pub fn measure_cpu(scenario: &Scenario, ticks: u64) -> Result<Measurement, Box<dyn Error>> {
+    let mut world = scenario.to_world()?;
+    let mut total_time = Duration::default();
+
+    for _ in 0..ticks {
+        let tick_start = Instant::now();
+        world.tick();
+        total_time += tick_start.elapsed();
+    }
+
+    Ok(Measurement {
+        backend: Backend::Cpu,
+        width: scenario.width,
+        height: scenario.height,
+        ticks,
+        total_time,
+    })
+}
+
+/// Writes measurements as CSV rows, `benches/plot.py compare` reads them back
+pub fn write_csv<P: AsRef<Path>>(path: P, measurements: &[Measurement]) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "backend,width,height,ticks,tick_time_us,ticks_per_second,cells_per_second")?;
+
+    for m in measurements {
+        writeln!(
+            out,
+            "{},{},{},{},{:.2},{:.2},{:.0}",
+            m.backend,
+            m.width,
+            m.height,
+            m.ticks,
+            m.tick_time().as_nanos() as f64 / 1000.0,
+            m.ticks_per_second(),
+            m.throughput(),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_measurement_rates() {
+    let measurement = Measurement {
+        backend: Backend::Cpu,
+        width: 100,
+        height: 10,
+        ticks: 50,
+        total_time: Duration::from_millis(500),
+    };
+
+    assert_eq!(measurement.tick_time(), Duration::from_millis(10));
+    assert!((measurement.ticks_per_second() - 100.0).abs() < 1e-9);
+    assert!((measurement.throughput() - 100_000.0).abs() < 1e-6);
+
+    let empty = Measurement { ticks: 0, ..measurement };
+    assert_eq!(empty.tick_time(), Duration::ZERO);
+    assert_eq!(empty.ticks_per_second(), 0.0);
+}
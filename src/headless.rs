@@ -0,0 +1,162 @@
+//! Runs a simulation for a fixed number of ticks without a window.
+//!
+//! Everything is written to an output directory: the final state as
+//! `final.txt`, optional snapshots as `snapshot_<tick>.txt`, both in the text
+//! grid format, and optionally a CSV of per tick statistics. The states can
+//! be saved as PNG images next to the text grids, and the whole run recorded
+//! as a GIF or Y4M clip.
+
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::record::{Format, RecordOptions, Recorder};
+use crate::simulation::{self, Simulation};
+use crate::stats::{Stats, StatsWriter};
+
+pub struct RunOptions {
+    pub ticks: u64,
+    /// Write a snapshot every N ticks
+    pub snapshot_every: Option<u64>,
+    pub output_dir: PathBuf,
+    pub stats_path: Option<PathBuf>,
+    /// Also save the final state and the snapshots as PNG images, with this many pixels per cell
+    pub image_scale: Option<usize>,
+    /// Record the run to this `.gif` or `.y4m` file, starting with the initial state
+    pub recording: Option<PathBuf>,
+    pub record: RecordOptions,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            ticks: 0,
+            snapshot_every: None,
+            output_dir: PathBuf::from("."),
+            stats_path: None,
+            image_scale: None,
+            recording: None,
+            record: RecordOptions::default(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Summary {
+    pub ticks: u64,
+    pub elapsed: Duration,
+    pub initial_mass: f32,
+    pub final_mass: f32,
+}
+
+pub fn run(simulation: &mut dyn Simulation, options: &RunOptions) -> Result<Summary, Box<dyn Error>> {
+    fs::create_dir_all(&options.output_dir)?;
+
+    let mut stats = match &options.stats_path {
+        Some(path) => Some(StatsWriter::create(path)?),
+        None => None,
+    };
+
+    let mut recorder = match &options.recording {
+        Some(path) => Some(Recorder::create(path, Format::from_path(path)?, simulation.size(), options.record)?),
+        None => None,
+    };
+    if let Some(recorder) = recorder.as_mut() {
+        recorder.frame(0, &simulation.render())?;
+    }
+
+    let initial_mass = simulation.total_mass();
+    let mut elapsed = Duration::default();
+
+    for tick in 1..=options.ticks {
+        let tick_start = Instant::now();
+        simulation.tick();
+        let tick_time = tick_start.elapsed();
+        elapsed += tick_time;
+
+        if let Some(stats) = stats.as_mut() {
+            stats.write(&Stats {
+                tick,
+                tick_time,
+                total_mass: simulation.total_mass(),
+            })?;
+        }
+
+        if let Some(recorder) = recorder.as_mut().filter(|recorder| recorder.wants(tick)) {
+            recorder.frame(tick, &simulation.render())?;
+        }
+
+        if options.snapshot_every.is_some_and(|every| tick % every == 0) {
+            let path = options.output_dir.join(format!("snapshot_{:06}.txt", tick));
+            fs::write(&path, simulation.to_grid().to_string())?;
+
+            if let Some(scale) = options.image_scale {
+                simulation::save_image(simulation, &path.with_extension("png"), scale)?;
+            }
+        }
+    }
+
+    fs::write(options.output_dir.join("final.txt"), simulation.to_grid().to_string())?;
+
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+
+    if let Some(scale) = options.image_scale {
+        simulation::save_image(simulation, &options.output_dir.join("final.png"), scale)?;
+    }
+
+    Ok(Summary {
+        ticks: options.ticks,
+        elapsed,
+        initial_mass,
+        final_mass: simulation.total_mass(),
+    })
+}
+
+#[test]
+fn test_run_writes_snapshots() {
+    use crate::ascii::Grid;
+
+    let output_dir = std::env::temp_dir().join(format!("automata-headless-{}", std::process::id()));
+    let grid: Grid = "
+        #####
+        #.~.#
+        #...#
+        #...#
+        #####
+    ".parse().unwrap();
+    let mut world = grid.to_world().unwrap();
+
+    let options = RunOptions {
+        ticks: 10,
+        snapshot_every: Some(5),
+        output_dir: output_dir.clone(),
+        stats_path: None,
+        image_scale: Some(2),
+        recording: Some(output_dir.join("run.gif")),
+        record: RecordOptions { stride: 5, ..RecordOptions::default() },
+    };
+    let summary = run(&mut world, &options).unwrap();
+
+    assert_eq!(summary.ticks, 10);
+    assert!(output_dir.join("snapshot_000005.txt").exists());
+    assert!(output_dir.join("snapshot_000010.txt").exists());
+    assert!(output_dir.join("snapshot_000010.png").exists());
+
+    // 5 by 5 cells at two pixels each
+    let png = fs::read(output_dir.join("final.png")).unwrap();
+    assert_eq!(&png[16..24], &[0, 0, 0, 10, 0, 0, 0, 10]);
+
+    // The initial state and ticks 5 and 10, every frame starts with a graphic control extension
+    let gif = fs::read(output_dir.join("run.gif")).unwrap();
+    assert_eq!(&gif[..6], b"GIF89a");
+    assert_eq!(gif.windows(3).filter(|bytes| bytes == &[0x21, 0xf9, 4]).count(), 3);
+    assert_eq!(gif.last(), Some(&0x3b));
+
+    let last: Grid = fs::read_to_string(output_dir.join("final.txt")).unwrap().parse().unwrap();
+    assert_eq!(last.to_string(), world.to_grid().to_string());
+
+    fs::remove_dir_all(output_dir).unwrap();
+}
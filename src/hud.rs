@@ -0,0 +1,264 @@
+//! Heads-up display shared by both frontends.
+//!
+//! The state is written with a built-in 3x5 pixel font into a small image of a
+//! fixed size. The CPU frontend copies that image onto its frame buffer, the
+//! GPU frontend uploads it and draws it in an overlay pass.
+
+use std::time::{Duration, Instant};
+
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+/// Pixels between two glyphs and between two lines
+const SPACING: usize = 1;
+/// Pixels between the border of the image and the text
+const PADDING: usize = 2;
+
+/// Characters per line, longer lines are cut off
+pub const COLUMNS: usize = 20;
+pub const ROWS: usize = 8;
+
+pub const WIDTH: usize = 2 * PADDING + COLUMNS * (GLYPH_WIDTH + SPACING) - SPACING;
+pub const HEIGHT: usize = 2 * PADDING + ROWS * (GLYPH_HEIGHT + SPACING) - SPACING;
+
+pub const BACKGROUND: u32 = 0x202020;
+pub const FOREGROUND: u32 = 0xffffff;
+
+/// The rows of a glyph from top to bottom, the highest of the three bits is
+/// the left column. Lowercase letters are drawn as uppercase ones and unknown
+/// characters as a question mark.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+/// Width in pixels of `text` written with `draw_text`
+pub fn text_width(text: &str) -> usize {
+    (text.chars().count() * (GLYPH_WIDTH + SPACING)).saturating_sub(SPACING)
+}
+
+/// Writes `text` with its top left corner at `(x, y)` into a buffer of
+/// `0xRRGGBB` pixels that is `width` pixels wide. Pixels falling outside of
+/// the buffer are skipped.
+pub fn draw_text(buff: &mut [u32], width: usize, (x, y): (usize, usize), text: &str, color: u32) {
+    let height = buff.len() / width;
+
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i * (GLYPH_WIDTH + SPACING);
+
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                let (px, py) = (left + column, y + row);
+
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 && px < width && py < height {
+                    buff[px + py * width] = color;
+                }
+            }
+        }
+    }
+}
+
+/// Copies an image that is `image_width` pixels wide onto a buffer that is
+/// `width` pixels wide, with its top left corner at `(x, y)`
+pub fn blit(buff: &mut [u32], width: usize, image: &[u32], image_width: usize, (x, y): (usize, usize)) {
+    let height = buff.len() / width;
+
+    for (row, line) in image.chunks(image_width).enumerate() {
+        if y + row >= height || x >= width {
+            break;
+        }
+
+        let visible = line.len().min(width - x);
+        let start = x + (y + row) * width;
+        buff[start..start + visible].copy_from_slice(&line[..visible]);
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let micros = duration.as_secs_f64() * 1e6;
+
+    if micros < 1000.0 {
+        format!("{:.0}US", micros)
+    } else {
+        format!("{:.2}MS", micros / 1000.0)
+    }
+}
+
+/// Everything the HUD shows
+pub struct Hud {
+    pub fps: f32,
+    /// Ticks run per second, measured like the frame rate
+    pub tps: f32,
+    /// Time of a single tick
+    pub tick_time: Duration,
+    pub total_mass: f32,
+    pub tick: u64,
+    pub brush_size: f32,
+    /// Name of the selected element
+    pub element: &'static str,
+    /// Name of the debug view, see `views::View`
+    pub view: &'static str,
+    pub paused: bool,
+    pub recording: bool,
+    /// Speed and ticks per frame, see `Stepper::status`
+    pub stepping: String,
+}
+
+impl Hud {
+    pub fn lines(&self) -> [String; ROWS] {
+        [
+            format!("FPS {:.0} TPS {:.0}", self.fps, self.tps),
+            format!("TICK {}", self.tick),
+            format!("TICK TIME {}", format_duration(self.tick_time)),
+            format!("MASS {:.1}", self.total_mass),
+            format!("BRUSH {:.0} {}", self.brush_size, self.element),
+            format!("VIEW {}", self.view),
+            format!("{}{}", if self.paused { "PAUSED" } else { "RUNNING" }, if self.recording { " REC" } else { "" }),
+            self.stepping.clone(),
+        ]
+    }
+
+    /// The HUD as an image of `WIDTH` by `HEIGHT` pixels in `0xRRGGBB`
+    pub fn render(&self) -> Vec<u32> {
+        let mut image = vec![BACKGROUND; WIDTH * HEIGHT];
+
+        for (row, line) in self.lines().iter().enumerate() {
+            let line: String = line.chars().take(COLUMNS).collect();
+            let y = PADDING + row * (GLYPH_HEIGHT + SPACING);
+
+            draw_text(&mut image, WIDTH, (PADDING, y), &line, FOREGROUND);
+        }
+
+        image
+    }
+}
+
+/// Counts rendered frames, or with `count` the ticks run, and averages them
+/// over half a second
+pub struct FpsCounter {
+    frames: u32,
+    since: Instant,
+    fps: f32,
+}
+
+impl FpsCounter {
+    pub fn new() -> Self {
+        FpsCounter {
+            frames: 0,
+            since: Instant::now(),
+            fps: 0.0,
+        }
+    }
+
+    /// Counts a frame, returns whether the average was updated
+    pub fn frame(&mut self) -> bool {
+        self.count(1)
+    }
+
+    /// Counts `frames` at once, called every frame so the average keeps
+    /// updating when there were none
+    pub fn count(&mut self, frames: u32) -> bool {
+        self.frames += frames;
+
+        let elapsed = self.since.elapsed();
+        if elapsed < Duration::from_millis(500) {
+            return false;
+        }
+
+        self.fps = self.frames as f32 / elapsed.as_secs_f32();
+        self.frames = 0;
+        self.since = Instant::now();
+
+        true
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+}
+
+impl Default for FpsCounter {
+    fn default() -> Self {
+        FpsCounter::new()
+    }
+}
+
+#[test]
+fn test_draw_text_clips_to_the_buffer() {
+    let mut buff = vec![0; 6 * 4];
+
+    // "1" at the right edge, only its left column fits and the bottom row is cut off
+    draw_text(&mut buff, 6, (5, 0), "11", 1);
+
+    let lit: Vec<usize> = (0..buff.len()).filter(|&i| buff[i] == 1).collect();
+    assert_eq!(lit, vec![6 + 5]);
+}
+
+#[test]
+fn test_hud_lines_fit_the_image() {
+    let hud = Hud {
+        fps: 59.9,
+        tps: 12_000.0,
+        tick_time: Duration::from_micros(420),
+        total_mass: 1234.5,
+        tick: 1_000_000,
+        brush_size: 20.0,
+        element: "water",
+        view: "pressure",
+        paused: true,
+        recording: true,
+        stepping: "SPEED X0.125 64 TPF".to_string(),
+    };
+
+    assert_eq!(hud.lines()[0], "FPS 60 TPS 12000");
+    assert_eq!(hud.lines()[2], "TICK TIME 420US");
+    assert_eq!(hud.lines()[6], "PAUSED REC");
+    assert!(hud.lines().iter().all(|line| line.chars().count() <= COLUMNS));
+    assert_eq!(hud.render().len(), WIDTH * HEIGHT);
+}
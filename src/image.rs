@@ -0,0 +1,220 @@
+//! Saving frames as PNG files.
+//!
+//! Images are buffers of `0xRRGGBB` pixels with the top row first, like the
+//! frame buffers of both frontends. The encoder writes 8 bit RGB without any
+//! compression: the deflate stream only holds stored blocks, which every
+//! decoder reads and which needs no compression library.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+/// Largest payload of a stored deflate block
+const MAX_STORED: usize = 0xffff;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// A zlib stream of stored blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window, no preset dictionary, header check bits set
+    let mut stream = vec![0x78, 0x01];
+    let blocks = data.len().div_ceil(MAX_STORED).max(1);
+
+    for i in 0..blocks {
+        let block = &data[i * MAX_STORED..((i + 1) * MAX_STORED).min(data.len())];
+        let length = block.len() as u16;
+
+        stream.push((i + 1 == blocks) as u8);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+/// Encodes an image of `width` by `height` pixels as a PNG file
+pub fn encode_png(width: usize, height: usize, pixels: &[u32]) -> Vec<u8> {
+    assert_eq!(pixels.len(), width * height);
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, deflate, no filtering beyond the per row byte, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut raw = Vec::with_capacity(height * (1 + 3 * width));
+    for row in pixels.chunks(width.max(1)) {
+        // Filter type None
+        raw.push(0);
+        for &pixel in row {
+            raw.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+        }
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+pub fn save_png(path: &Path, width: usize, height: usize, pixels: &[u32]) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, encode_png(width, height, pixels))?;
+
+    Ok(())
+}
+
+/// Enlarges an image that is `width` pixels wide, every pixel becomes a
+/// square of `scale` by `scale` pixels
+pub fn scale(pixels: &[u32], width: usize, scale: usize) -> Vec<u32> {
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+
+    for row in pixels.chunks(width) {
+        let line: Vec<u32> = row.iter().flat_map(|&pixel| std::iter::repeat_n(pixel, scale)).collect();
+        for _ in 0..scale {
+            scaled.extend_from_slice(&line);
+        }
+    }
+
+    scaled
+}
+
+/// Year, month and day of a day counted from 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months counted from March, so the leap day comes last
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    (year, month, day)
+}
+
+/// `<prefix>_<date>_<time>.<extension>` in `dir`, with the UTC time of `time`
+/// down to the millisecond
+pub fn timestamped_path(dir: &Path, prefix: &str, extension: &str, time: SystemTime) -> PathBuf {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let seconds_of_day = seconds.rem_euclid(86_400);
+
+    dir.join(format!(
+        "{}_{:04}-{:02}-{:02}_{:02}-{:02}-{:02}.{:03}.{}",
+        prefix,
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis(),
+        extension,
+    ))
+}
+
+#[cfg(test)]
+fn inflate_stored(stream: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut at = 2;
+
+    loop {
+        let last = stream[at] & 1 != 0;
+        let length = u16::from_le_bytes([stream[at + 1], stream[at + 2]]) as usize;
+        data.extend_from_slice(&stream[at + 5..at + 5 + length]);
+        at += 5 + length;
+
+        if last {
+            assert_eq!(&stream[at..], &adler32(&data).to_be_bytes());
+            return data;
+        }
+    }
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+}
+
+#[test]
+fn test_png_round_trips_through_stored_blocks() {
+    // Large enough for two stored blocks
+    let (width, height) = (200, 120);
+    let pixels: Vec<u32> = (0..width * height).map(|i| (i as u32).wrapping_mul(2_654_435_761) & 0xffffff).collect();
+    let png = encode_png(width, height, &pixels);
+
+    assert_eq!(&png[..8], &SIGNATURE);
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(&png[16..24], &[0, 0, 0, 200, 0, 0, 0, 120]);
+
+    let idat_length = u32::from_be_bytes([png[33], png[34], png[35], png[36]]) as usize;
+    assert_eq!(&png[37..41], b"IDAT");
+    let raw = inflate_stored(&png[41..41 + idat_length]);
+
+    let row = &raw[(1 + 3 * width) * 7..(1 + 3 * width) * 8];
+    let pixel = pixels[7 * width + 5];
+    assert_eq!(row[0], 0);
+    assert_eq!(&row[1 + 3 * 5..4 + 3 * 5], &[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+    assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+}
+
+#[test]
+fn test_scale_repeats_pixels() {
+    assert_eq!(scale(&[1, 2, 3, 4], 2, 2), vec![1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]);
+}
+
+#[test]
+fn test_timestamped_path() {
+    let time = UNIX_EPOCH + std::time::Duration::from_millis(1_792_348_908_042);
+    let path = timestamped_path(Path::new("shots"), "screenshot", "png", time);
+
+    assert_eq!(path, Path::new("shots/screenshot_2026-10-18_18-41-48.042.png"));
+    assert_eq!(civil_from_days(951_782_400 / 86_400), (2000, 2, 29));
+}
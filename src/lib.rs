@@ -1,41 +1,191 @@
 use std::sync::mpsc::{Receiver, channel};
 use std::error::Error;
+use std::fmt;
 use std::cmp::min;
 use std::mem::swap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
 
-use glfw::{Context, WindowHint, WindowEvent, Key, Action, CursorMode};
+use glfw::{Context, WindowHint, WindowEvent, Key, Action, CursorMode, Modifiers};
 use glw::shader::ShaderType;
 use glw::buffers::StructuredBuffer;
 use glw::{Color, RenderTarget, Shader, Uniform, Vec2, MemoryBarrier};
-use rand::Rng;
 use std::borrow::Borrow;
 
-const WINDOW_WIDTH: u32 = 512;
-const WINDOW_HEIGHT: u32 = 512;
+pub mod ascii;
+pub mod camera;
+pub mod cave;
+pub mod compare;
+pub mod headless;
+pub mod hud;
+pub mod image;
+pub mod palette;
+pub mod params;
+pub mod raster;
+pub mod record;
+pub mod reference;
+pub mod scenario;
+pub mod scheduler;
+pub mod shapes;
+pub mod simulation;
+pub mod stats;
+pub mod stepping;
+pub mod tools;
+pub mod validation;
+pub mod viewport;
+pub mod views;
+pub mod world;
+
+use rand::prng::XorShiftRng;
+use rand::{FromEntropy, SeedableRng};
+
+use crate::ascii::Grid;
+use crate::camera::Camera;
+use crate::cave::CaveSettings;
+use crate::compare::Measurement;
+use crate::hud::{FpsCounter, Hud};
+use crate::params::Params;
+use crate::record::{Format, RecordOptions, Recorder};
+use crate::scenario::{Emitter, Scenario};
+use crate::scheduler::Scheduler;
+use crate::stats::{Stats, StatsWriter};
+use crate::stepping::Stepper;
+use crate::tools::{BrushMode, Tool};
+use crate::viewport::Viewport;
+use crate::views::View;
+
 const FIELD_WIDTH: i32 = 256;
 const FIELD_HEIGHT: i32 = 256;
-const WIDTH: usize = FIELD_WIDTH as usize;
-const HEIGHT: usize = FIELD_HEIGHT as usize;
 
-#[derive(Copy, Clone)]
+/// Which implementation of the flow rules to run
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Backend {
+    /// `World::tick`, rendered with minifb
+    Cpu,
+    /// The compute shader, rendered with OpenGL
+    Gpu,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpu" => Ok(Backend::Cpu),
+            "gpu" => Ok(Backend::Gpu),
+            _ => Err(format!("unknown backend '{}', expected cpu or gpu", s)),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // `pad` so the name lines up in tables
+        f.pad(match self {
+            Backend::Cpu => "cpu",
+            Backend::Gpu => "gpu",
+        })
+    }
+}
+
+/// Start up settings of the GPU frontend
+pub struct Config {
+    pub field_width: usize,
+    pub field_height: usize,
+    /// Window pixels per field cell
+    pub window_scale: u32,
+    /// Updates per second of the fixed timestep
+    pub update_rate: f64,
+    /// Longest backlog of updates a frame catches up on, see `Scheduler`
+    pub max_lag: Duration,
+    /// Compute dispatches every update
+    pub ticks_per_frame: u32,
+    /// Wait for the vertical blank before showing a frame
+    pub vsync: bool,
+    /// Water the brush pours or drains per cell and tick, in full cells
+    pub flow_rate: f32,
+    pub seed: Option<u64>,
+    /// Stop the simulation after this many ticks
+    pub tick_limit: Option<u64>,
+    pub scenario: Option<Scenario>,
+    /// A saved text grid, takes precedence over the scenario
+    pub grid: Option<Grid>,
+    pub stats_path: Option<PathBuf>,
+    /// Where screenshots and recordings are saved
+    pub screenshot_dir: PathBuf,
+    /// Format of the recordings started with F10
+    pub record_format: Format,
+    pub record: RecordOptions,
+    /// Show the window, measurements run with a hidden one
+    pub visible: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            field_width: FIELD_WIDTH as usize,
+            field_height: FIELD_HEIGHT as usize,
+            window_scale: 2,
+            update_rate: 400.0,
+            max_lag: scheduler::DEFAULT_MAX_LAG,
+            ticks_per_frame: 1,
+            vsync: false,
+            flow_rate: tools::DEFAULT_FLOW_RATE,
+            seed: None,
+            tick_limit: None,
+            scenario: None,
+            grid: None,
+            stats_path: None,
+            screenshot_dir: PathBuf::from("."),
+            record_format: Format::Gif,
+            record: RecordOptions::default(),
+            visible: true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(i32)]
-enum CellType {
+pub enum CellType {
     Empty = 0,
     Block = 1,
     Water = 2,
     Acid = 3,
 }
 
+impl CellType {
+    pub fn from_i32(element_type: i32) -> Option<Self> {
+        match element_type {
+            0 => Some(CellType::Empty),
+            1 => Some(CellType::Block),
+            2 => Some(CellType::Water),
+            3 => Some(CellType::Acid),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CellType::Empty => "empty",
+            CellType::Block => "block",
+            CellType::Water => "water",
+            CellType::Acid => "acid",
+        }
+    }
+}
+
 impl Default for CellType {
     fn default() -> Self {
         CellType::Empty
     }
 }
 
-#[derive(Copy, Clone)]
-struct Cell {
-    element_type: i32,
-    mass: f32,
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub struct Cell {
+    pub element_type: i32,
+    pub mass: f32,
 }
 
 impl Default for Cell {
@@ -59,6 +209,80 @@ fn clamp<T: PartialOrd>(val: T, min: T, max: T) -> T {
     }
 }
 
+/// Index of the cell at `(x, y)` in a field buffer, `None` outside of the field
+fn cell_index(field_size: &Vec2<i32>, (x, y): (i32, i32)) -> Option<usize> {
+    if x < 0 || y < 0 || x >= field_size.x || y >= field_size.y {
+        return None;
+    }
+
+    Some((x + y * field_size.x) as usize)
+}
+
+/// Framebuffer pixels per HUD pixel on a screen of normal density
+const HUD_SCALE: i32 = 2;
+/// Times batches of dispatches with a timer query, without waiting for them.
+/// A new batch is only timed once the result of the previous one arrived.
+struct TickTimer {
+    query: u32,
+    /// Dispatches covered by the query in flight
+    pending: Option<u32>,
+    tick_time: Duration,
+}
+
+impl TickTimer {
+    fn new() -> Self {
+        let mut query = 0;
+        unsafe { gl::GenQueries(1, &mut query); }
+
+        TickTimer {
+            query,
+            pending: None,
+            tick_time: Duration::from_secs(0),
+        }
+    }
+
+    /// Whether the next batch is timed, `end` has to follow it then
+    fn begin(&mut self) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+
+        unsafe { gl::BeginQuery(gl::TIME_ELAPSED, self.query); }
+        true
+    }
+
+    fn end(&mut self, ticks: u32) {
+        unsafe { gl::EndQuery(gl::TIME_ELAPSED); }
+        self.pending = Some(ticks);
+    }
+
+    /// Time of a single tick from the latest batch whose result arrived
+    fn tick_time(&mut self) -> Duration {
+        if let Some(ticks) = self.pending {
+            let mut available = 0;
+            unsafe { gl::GetQueryObjectiv(self.query, gl::QUERY_RESULT_AVAILABLE, &mut available); }
+
+            if available != 0 {
+                let mut elapsed_ns = 0u64;
+                unsafe { gl::GetQueryObjectui64v(self.query, gl::QUERY_RESULT, &mut elapsed_ns); }
+
+                if ticks > 0 {
+                    self.tick_time = Duration::from_nanos(elapsed_ns / ticks as u64);
+                }
+                self.pending = None;
+            }
+        }
+
+        self.tick_time
+    }
+}
+
+impl Drop for TickTimer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteQueries(1, &self.query); }
+    }
+}
+
 struct Application {
     // GLFW Setup
     glfw: glfw::Glfw,
@@ -72,29 +296,52 @@ struct Application {
     curr_sb: StructuredBuffer<Cell>,
     prev_sb: StructuredBuffer<Cell>,
     tmp_sb: StructuredBuffer<f32>,
+    emitters_sb: StructuredBuffer<Emitter>,
+
+    params: Params,
+    emitter_count: usize,
+    tick_limit: Option<u64>,
+    update_rate: f64,
+    max_lag: Duration,
+    ticks_per_frame: u32,
+    vsync: bool,
+    flow_rate: f32,
+    rng: XorShiftRng,
+    stats: Option<StatsWriter>,
 
     compute_program: glw::GraphicsPipeline,
     render_program: glw::GraphicsPipeline,
+    hud_program: glw::GraphicsPipeline,
+    hud_sb: StructuredBuffer<u32>,
 
     // Quad mesh
     quad: glw::Mesh,
 
     // Program states
-    is_paused: bool,
+    screenshot_dir: PathBuf,
+    record_format: Format,
+    record: RecordOptions,
     gl_ctx: glw::GLContext,
 }
 
 impl Application {
-    fn new() -> Result<Application, Box<dyn Error>> {
+    fn new(config: Config) -> Result<Application, Box<dyn Error>> {
+        let (field_width, field_height) = match (&config.grid, &config.scenario) {
+            (Some(grid), _) => (grid.width, grid.height),
+            (None, Some(scenario)) => (scenario.width, scenario.height),
+            (None, None) => (config.field_width, config.field_height),
+        };
+
         let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS)?;
 
-        glfw.window_hint(WindowHint::Resizable(false));
+        glfw.window_hint(WindowHint::Resizable(true));
+        glfw.window_hint(WindowHint::Visible(config.visible));
         glfw.window_hint(WindowHint::ContextVersion(4, 5));
         glfw.window_hint(WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
 
         let (mut window, events) = glfw.create_window(
-            WINDOW_WIDTH,
-            WINDOW_HEIGHT,
+            field_width as u32 * config.window_scale,
+            field_height as u32 * config.window_scale,
             "CA Simulation",
             glfw::WindowMode::Windowed,
         ).unwrap();
@@ -123,7 +370,9 @@ impl Application {
         window.set_cursor_pos_polling(true);
         window.set_scroll_polling(true);
 
-        window.show();
+        if config.visible {
+            window.show();
+        }
 
         let vertices: [f32; 32] = [
             -1.0, -1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0,
@@ -155,6 +404,19 @@ impl Application {
                 .build()
         };
 
+        let hud_program = {
+            let mut v_shader = Shader::new(ShaderType::Vertex);
+            let mut f_shader = Shader::new(ShaderType::Fragment);
+
+            v_shader.load_from_file("shaders/passthrough.vert").unwrap();
+            f_shader.load_from_file("shaders/hud.frag").unwrap();
+
+            glw::PipelineBuilder::new()
+                .with_vertex_shader(v_shader)
+                .with_fragment_shader(f_shader)
+                .build()
+        };
+
         let compute_program = {
             let mut c_shader = Shader::new(ShaderType::Compute);
 
@@ -166,28 +428,46 @@ impl Application {
         };
 
         let field_size = Vec2::<i32> {
-            x: FIELD_WIDTH,
-            y: FIELD_HEIGHT,
+            x: field_width as i32,
+            y: field_height as i32,
         };
 
-        let image_data = Application::generate_map(&field_size);
+        let rng = match config.seed {
+            Some(seed) => XorShiftRng::seed_from_u64(seed),
+            None => XorShiftRng::from_entropy(),
+        };
 
-        let prev_sb = StructuredBuffer::from(*image_data);
-        let curr_sb = StructuredBuffer::new((field_size.x * field_size.y) as usize);
+        let (image_data, params, emitters, tick_limit) = match (config.grid, config.scenario) {
+            (Some(grid), _) => (grid.to_cells(), Params::gpu(), Vec::new(), None),
+            (None, Some(mut scenario)) => {
+                if config.seed.is_some() {
+                    scenario.seed = config.seed;
+                }
 
-        let mut tmp_vec = Box::new(vec![0.0f32; (field_size.x * field_size.y) as usize]);
+                (scenario.to_cells(), scenario.params(Params::gpu()), scenario.gpu_emitters(), scenario.ticks)
+            }
+            (None, None) => (*Application::generate_map(&field_size), Params::gpu(), Vec::new(), None),
+        };
 
-        tmp_vec[129 + 190 * 256] = 1.0;
-        tmp_vec[129 + 200 * 256] = 1.0;
-        tmp_vec[129 + 210 * 256] = 1.0;
-        tmp_vec[129 + 180 * 256] = 1.0;
-        tmp_vec[129 + 170 * 256] = 1.0;
-        tmp_vec[129 + 160 * 256] = 1.0;
-        tmp_vec[129 + 120 * 256] = 1.0;
-        tmp_vec[132 + 190 * 256] = 1.0;
-        tmp_vec[126 + 196 * 256] = 1.0;
+        let stats = match config.stats_path {
+            Some(path) => Some(StatsWriter::create(path)?),
+            None => None,
+        };
 
-        let tmp_sb = StructuredBuffer::from(*tmp_vec);
+        // The compute shader keeps the mass of every cell in a separate buffer
+        let tmp_vec: Vec<f32> = image_data.iter().map(|cell| cell.mass).collect();
+
+        let prev_sb = StructuredBuffer::from(image_data);
+        let curr_sb = StructuredBuffer::new((field_size.x * field_size.y) as usize);
+        let tmp_sb = StructuredBuffer::from(tmp_vec);
+
+        // Never allocate an empty buffer, the shader only reads `u_emitter_count` of them
+        let emitter_count = emitters.len();
+        let emitters_sb = if emitters.is_empty() {
+            StructuredBuffer::new(1)
+        } else {
+            StructuredBuffer::from(emitters)
+        };
 
         Ok(Application {
             glfw,
@@ -197,21 +477,35 @@ impl Application {
             curr_sb,
             prev_sb,
             tmp_sb,
+            emitters_sb,
+            params,
+            emitter_count,
+            tick_limit: config.tick_limit.or(tick_limit),
+            update_rate: config.update_rate,
+            max_lag: config.max_lag,
+            ticks_per_frame: config.ticks_per_frame,
+            vsync: config.vsync,
+            flow_rate: config.flow_rate,
+            rng,
+            stats,
             compute_program,
             render_program,
+            hud_program,
+            hud_sb: StructuredBuffer::new(hud::WIDTH * hud::HEIGHT),
             quad,
-            is_paused: false,
+            screenshot_dir: config.screenshot_dir,
+            record_format: config.record_format,
+            record: config.record,
             gl_ctx: ctx,
         })
     }
 
     fn run(&mut self) -> Result<(), Box<dyn Error>> {
-        self.glfw.set_swap_interval(glfw::SwapInterval::None);
+        self.glfw.set_swap_interval(if self.vsync { glfw::SwapInterval::Sync(1) } else { glfw::SwapInterval::None });
 
-        let update_time = 1.0 / 400.0;
-        // let update_time = 0.0;
+        let mut scheduler = Scheduler::new(self.update_rate, self.max_lag);
+        let update_time = scheduler.step().as_secs_f32();
 
-        let mut timer = 0.0;
         let mut time = self.get_time();
 
         let mut drawing_cell = 0;
@@ -220,33 +514,80 @@ impl Application {
         let mut mouse_y = 0.0;
         let mut brush_size = 1.0;
         let mut rotation_signal = 0;
+        let mut tick_count: u64 = 0;
+        let mut tool = Tool::Brush;
+        let mut brush_mode = BrushMode::Paint;
+        // The right button erases with whichever tool is selected
+        let mut erasing = false;
+        let mut show_hud = true;
+        let mut view = View::Normal;
+        let mut smooth_surface = false;
+        let mut take_screenshot = false;
+        let mut toggle_recording = false;
+        let mut recorder: Option<Recorder> = None;
+        let mut camera = Camera::new(self.field_size.x as usize, self.field_size.y as usize);
+        let mut fps = FpsCounter::new();
+        let mut tps = FpsCounter::new();
+        let mut tick_timer = TickTimer::new();
+        let mut stepper = Stepper::new(self.ticks_per_frame);
+        let mut total_mass = 0.0;
+        let mut drag_start = None;
+        // The cell the brush last painted, the next dispatch paints the segment from there to the cursor
+        let mut stroke_start = (0, 0);
 
         while !self.window.should_close() {
-            let (width, height) = self.window.get_size();
-            let middle = width / 2;
-            let width = width.min(height);
+            // Fitted every frame, the window may have been resized or moved to a screen of another density
+            let viewport = Viewport::fit(
+                self.window.get_size(),
+                self.window.get_framebuffer_size(),
+                (self.field_size.x, self.field_size.y),
+            );
 
-            self.gl_ctx.set_viewport(middle - width / 2, 0, width, width);
+            self.gl_ctx.set_viewport(viewport.x, viewport.y, viewport.width, viewport.height);
 
             let prev_time = time;
             time = self.get_time();
 
             let dt = time - prev_time;
-            timer -= dt;
+            // Counted while paused too, so resuming does not catch up on the pause
+            let updates = scheduler.advance(Duration::from_secs_f64(dt.max(0.0)));
 
             self.glfw.poll_events();
 
 
-            for (_, event) in glfw::flush_messages(&self.events) {
+            // Collected first, painting needs the whole application mutably
+            let events: Vec<_> = glfw::flush_messages(&self.events).collect();
+
+            for (_, event) in events {
                 match event {
                     WindowEvent::Key(Key::Escape, _, Action::Press, _) => self.window.set_should_close(true),
-                    WindowEvent::Key(Key::P, _, Action::Press, _) => self.is_paused = !self.is_paused,
+                    WindowEvent::Key(Key::P, _, Action::Press, _) => stepper.toggle_pause(),
+                    WindowEvent::Key(Key::Period, _, Action::Press | Action::Repeat, mods) if mods.contains(Modifiers::Shift) => {
+                        stepper.step(stepping::STEP_COUNT)
+                    }
+                    WindowEvent::Key(Key::Period, _, Action::Press | Action::Repeat, _) => stepper.step(1),
+                    WindowEvent::Key(Key::PageUp, _, Action::Press, mods) if mods.contains(Modifiers::Shift) => stepper.more_ticks_per_frame(),
+                    WindowEvent::Key(Key::PageDown, _, Action::Press, mods) if mods.contains(Modifiers::Shift) => stepper.fewer_ticks_per_frame(),
+                    WindowEvent::Key(Key::PageUp, _, Action::Press, _) => stepper.faster(),
+                    WindowEvent::Key(Key::PageDown, _, Action::Press, _) => stepper.slower(),
+                    WindowEvent::Key(Key::F, _, Action::Press, _) => stepper.toggle_fast_forward(),
+                    WindowEvent::Key(Key::H, _, Action::Press, _) => show_hud = !show_hud,
+                    WindowEvent::Key(Key::V, _, Action::Press, _) => view = view.next(),
+                    WindowEvent::Key(Key::W, _, Action::Press, _) => smooth_surface = !smooth_surface,
+                    WindowEvent::Key(Key::F12, _, Action::Press, _) => take_screenshot = true,
+                    WindowEvent::Key(Key::F10, _, Action::Press, _) => toggle_recording = true,
+                    // The field's y axis points up, like the arrows
+                    WindowEvent::Key(Key::Left, _, Action::Press | Action::Repeat, _) => camera.pan((-camera::PAN_STEP, 0.0)),
+                    WindowEvent::Key(Key::Right, _, Action::Press | Action::Repeat, _) => camera.pan((camera::PAN_STEP, 0.0)),
+                    WindowEvent::Key(Key::Up, _, Action::Press | Action::Repeat, _) => camera.pan((0.0, camera::PAN_STEP)),
+                    WindowEvent::Key(Key::Down, _, Action::Press | Action::Repeat, _) => camera.pan((0.0, -camera::PAN_STEP)),
+                    WindowEvent::Key(Key::Num0, _, Action::Press, _) => camera.reset(),
                     WindowEvent::Key(Key::C, _, Action::Press, _) => {
                         self.prev_sb.map_data(&Application::get_empty_field(&self.field_size));
                         self.tmp_sb.map_data(&vec![0.0f32; (self.field_size.x * self.field_size.y) as usize]);
                     }
                     WindowEvent::Key(Key::N, _, Action::Press, _) => {
-                        self.prev_sb.map_data(&Application::generate_cave());
+                        self.prev_sb.map_data(&Application::generate_cave(&self.field_size, &mut self.rng));
                         self.tmp_sb.map_data(&vec![0.0f32; (self.field_size.x * self.field_size.y) as usize]);
                     }
                     WindowEvent::Key(Key::R, _, Action::Press, _) => {
@@ -255,18 +596,56 @@ impl Application {
                     WindowEvent::Key(Key::Num1, _, Action::Press, _) => drawing_type = CellType::Block as i32,
                     WindowEvent::Key(Key::Num2, _, Action::Press, _) => drawing_type = CellType::Water as i32,
                     WindowEvent::Key(Key::Num3, _, Action::Press, _) => drawing_type = CellType::Acid as i32,
-                    WindowEvent::MouseButton(btn, action, mods) => {
+                    WindowEvent::Key(Key::B, _, Action::Press, _) => tool = Tool::Brush,
+                    WindowEvent::Key(Key::L, _, Action::Press, _) => tool = Tool::Line,
+                    WindowEvent::Key(Key::O, _, Action::Press, _) => tool = Tool::Rect,
+                    WindowEvent::Key(Key::S, _, Action::Press, _) => tool = Tool::FilledRect,
+                    WindowEvent::Key(Key::U, _, Action::Press, _) => tool = Tool::Ellipse,
+                    WindowEvent::Key(Key::G, _, Action::Press, _) => tool = Tool::Fill,
+                    WindowEvent::Key(Key::X, _, Action::Press, _) => tool = Tool::Eraser,
+                    WindowEvent::Key(Key::M, _, Action::Press, _) => brush_mode = brush_mode.next(),
+                    WindowEvent::Key(Key::Minus, _, Action::Press, _) => self.flow_rate = clamp(self.flow_rate / 2.0, tools::MIN_FLOW_RATE, tools::MAX_FLOW_RATE),
+                    WindowEvent::Key(Key::Equal, _, Action::Press, _) => self.flow_rate = clamp(self.flow_rate * 2.0, tools::MIN_FLOW_RATE, tools::MAX_FLOW_RATE),
+                    WindowEvent::Key(Key::LeftBracket, _, Action::Press, _) => brush_size = clamp(brush_size - 1.0, 1.0, tools::MAX_RADIUS),
+                    WindowEvent::Key(Key::RightBracket, _, Action::Press, _) => brush_size = clamp(brush_size + 1.0, 1.0, tools::MAX_RADIUS),
+                    WindowEvent::MouseButton(glfw::MouseButtonMiddle, Action::Press, _) => {
+                        // Eyedropper
+                        if let Some(cell) = self.cell_at(camera.to_cell(viewport.window_to_view(mouse_x, mouse_y))) {
+                            if CellType::from_i32(cell.element_type).is_some() {
+                                drawing_type = cell.element_type;
+                            }
+                        }
+                    }
+                    WindowEvent::MouseButton(btn @ (glfw::MouseButtonLeft | glfw::MouseButtonRight), action, _) => {
+                        let cursor = camera.to_cell(viewport.window_to_view(mouse_x, mouse_y));
+                        if action == Action::Press {
+                            erasing = btn == glfw::MouseButtonRight;
+                        }
+                        let element_type = if tool == Tool::Eraser || erasing { CellType::Empty as i32 } else { drawing_type };
+
                         match action {
-                            glfw::Action::Press => drawing_cell = 1,
-                            glfw::Action::Release => drawing_cell = 0,
+                            glfw::Action::Press if tool == Tool::Fill => self.flood_fill(cursor, element_type),
+                            glfw::Action::Press if tool.is_shape() => drag_start = Some(cursor),
+                            glfw::Action::Press => {
+                                drawing_cell = 1;
+                                stroke_start = cursor;
+                            }
+                            glfw::Action::Release => {
+                                drawing_cell = 0;
+                                if let Some(start) = drag_start.take() {
+                                    self.paint_cells(&tool.cells(start, cursor, brush_size), element_type);
+                                }
+                            }
                             _ => {}
                         }
-
-                        // println!("Button: {:?}, Action: {:?}, Modifiers: [{:?}]", glfw::DebugAliases(btn), action, mods);
+                    }
+                    WindowEvent::Scroll(_, y) if self.window.get_key(Key::LeftControl) == Action::Press
+                        || self.window.get_key(Key::RightControl) == Action::Press => {
+                        camera.zoom_at(camera::ZOOM_STEP.powf(y as f32), viewport.window_to_view(mouse_x, mouse_y));
                     }
                     WindowEvent::Scroll(x, y) => {
                         if y != 0.0 {
-                            brush_size = clamp(brush_size - y as f32, 1.0, 20.0);
+                            brush_size = clamp(brush_size - y as f32, 1.0, tools::MAX_RADIUS);
                         }
                     }
                     WindowEvent::CursorPos(xpos, ypos) => {
@@ -285,175 +664,434 @@ impl Application {
             self.gl_ctx.bind_rt(&RenderTarget::default());
             self.gl_ctx.clear(Some(Color::new(0, 0, 0, 0)));
 
-            if !self.is_paused && timer <= 0.0 {
-                timer = update_time;
+            if let Some(limit) = self.tick_limit {
+                if tick_count >= limit {
+                    stepper.pause();
+                }
+            }
+
+            // Steps queued while paused run right away
+            let ticks = stepper.ticks_for_updates(updates);
+            let fast_forward = stepper.fast_forward();
+            let ticks_before = tick_count;
+
+            if ticks > 0 || fast_forward {
+                let tick_start = Instant::now();
+                let cursor = camera.to_cell(viewport.window_to_view(mouse_x, mouse_y));
+                let brush_flow = if tool == Tool::Brush && !erasing { brush_mode.flow(self.flow_rate, self.params.max_mass) } else { 0.0 };
+                // A flow only covers the cells around the cursor, so the poured mass is exact
+                if brush_flow != 0.0 {
+                    stroke_start = cursor;
+                }
 
                 self.gl_ctx.bind_pipeline(&self.compute_program);
 
                 self.compute_program.set_uniform("u_resolution", Uniform::Vec2(self.field_size.x as f32, self.field_size.y as f32));
-                self.compute_program.set_uniform("u_dt", Uniform::Float(update_time as f32));
+                self.compute_program.set_uniform("u_dt", Uniform::Float(update_time));
                 self.compute_program.set_uniform("u_time", Uniform::Float(self.get_time() as f32));
                 self.compute_program.set_uniform("u_drawing", Uniform::Int(drawing_cell));
-                self.compute_program.set_uniform("u_drawing_type", Uniform::Int(drawing_type));
-                self.compute_program.set_uniform("u_mouse", Uniform::Vec2(mouse_x, mouse_y));
+                let brush_type = if tool == Tool::Eraser || erasing { CellType::Empty as i32 } else { drawing_type };
+                self.compute_program.set_uniform("u_drawing_type", Uniform::Int(brush_type));
+                self.compute_program.set_uniform("u_stroke_start", Uniform::Vec2(stroke_start.0 as f32, stroke_start.1 as f32));
+                self.compute_program.set_uniform("u_stroke_end", Uniform::Vec2(cursor.0 as f32, cursor.1 as f32));
                 self.compute_program.set_uniform("u_brush_size", Uniform::Float(brush_size));
-                self.compute_program.set_uniform("u_rotation_signal", Uniform::Int(rotation_signal));
+                self.compute_program.set_uniform("u_brush_flow", Uniform::Float(brush_flow));
+                self.compute_program.set_uniform("u_emitter_count", Uniform::Int(self.emitter_count as i32));
+                self.set_params_uniforms();
+
+                let timed = tick_timer.begin();
+                let mut batch_ticks = 0;
+
+                // Fast-forward keeps dispatching until it is time to show a frame
+                while batch_ticks < ticks || (fast_forward && tick_start.elapsed() < stepping::FAST_FORWARD_FRAME) {
+                    if self.tick_limit.is_some_and(|limit| tick_count >= limit) {
+                        break;
+                    }
 
-                self.compute_program.bind_storage_buffer(self.prev_sb.get_id(), 0);
-                self.compute_program.bind_storage_buffer(self.curr_sb.get_id(), 1);
-                self.compute_program.bind_storage_buffer(self.tmp_sb.get_id(), 2);
+                    self.compute_program.set_uniform("u_rotation_signal", Uniform::Int(rotation_signal));
+                    self.dispatch();
 
-                self.gl_ctx.dispatch_compute(
-                    self.field_size.x as u32 / 8,
-                    self.field_size.y as u32 / 8,
-                    1,
-                );
+                    rotation_signal = 0;
+                    tick_count += 1;
+                    batch_ticks += 1;
 
-                // FENCE and sync
+                    if fast_forward {
+                        // Otherwise the time would only cover queueing the dispatches
+                        unsafe { gl::Finish(); }
+                    }
+                }
+
+                if timed {
+                    tick_timer.end(batch_ticks);
+                }
 
-                self.gl_ctx.memory_barrier(MemoryBarrier::ShaderStorage);
+                stroke_start = cursor;
 
-                swap(&mut self.curr_sb, &mut self.prev_sb);
+                if self.stats.is_some() {
+                    // Wait for the dispatches so the measured time covers the actual work
+                    unsafe { gl::Finish(); }
 
-                rotation_signal = 0;
+                    let sample = Stats {
+                        tick: tick_count,
+                        tick_time: tick_start.elapsed() / batch_ticks.max(1),
+                        total_mass: self.read_mass().iter().sum(),
+                    };
+                    if let Some(stats) = self.stats.as_mut() {
+                        stats.write(&sample)?;
+                    }
+                }
             }
 
             self.gl_ctx.bind_pipeline(&self.render_program);
             self.render_program.set_uniform("u_resolution", Uniform::Vec2(self.field_size.x as f32, self.field_size.y as f32));
             self.render_program.set_uniform("u_time", Uniform::Float(self.get_time() as f32));
-            self.render_program.set_uniform("u_mouse", Uniform::Vec2(mouse_x, mouse_y));
+            let cursor = camera.to_cell(viewport.window_to_view(mouse_x, mouse_y));
+            self.render_program.set_uniform("u_mouse", Uniform::Vec2(cursor.0 as f32, cursor.1 as f32));
             self.render_program.set_uniform("u_brush_size", Uniform::Float(brush_size));
+            let (origin, visible_size) = (camera.origin(), camera.visible_size());
+            self.render_program.set_uniform("u_view_origin", Uniform::Vec2(origin.0, origin.1));
+            self.render_program.set_uniform("u_view_size", Uniform::Vec2(visible_size.0, visible_size.1));
+            let range = view.range(&self.params).unwrap_or((0.0, 1.0));
+            self.render_program.set_uniform("u_view", Uniform::Int(view as i32));
+            self.render_program.set_uniform("u_view_range", Uniform::Vec2(range.0, range.1));
+            self.render_program.set_uniform("u_smooth_surface", Uniform::Int(smooth_surface as i32));
+            self.render_program.set_uniform("u_max_mass", Uniform::Float(self.params.max_mass));
             self.render_program.bind_storage_buffer(self.prev_sb.get_id(), 0);
+            // After the swap the other buffer holds the generation before, the flow view compares the two
+            self.render_program.bind_storage_buffer(self.curr_sb.get_id(), 1);
             self.render_program.bind_storage_buffer(self.tmp_sb.get_id(), 2);
 
             self.quad.draw();
 
+            tps.count((tick_count - ticks_before) as u32);
+
+            // Reading the mass back stalls the pipeline, so it is only refreshed with the frame rate
+            if fps.frame() && show_hud {
+                total_mass = self.read_mass().iter().sum();
+            }
+
+            if show_hud {
+                let hud = Hud {
+                    fps: fps.fps(),
+                    tps: tps.fps(),
+                    tick_time: tick_timer.tick_time(),
+                    total_mass,
+                    tick: tick_count,
+                    brush_size,
+                    element: CellType::from_i32(drawing_type).map_or("unknown", CellType::name),
+                    view: view.name(),
+                    paused: stepper.is_paused(),
+                    recording: recorder.is_some(),
+                    stepping: stepper.status(),
+                };
+                self.draw_overlay(&hud.render(), (hud::WIDTH, hud::HEIGHT), false);
+
+                if let Some(range) = view.range(&self.params) {
+                    self.draw_overlay(&views::legend(view, range), (views::LEGEND_WIDTH, views::LEGEND_HEIGHT), true);
+                }
+            }
+
+            if take_screenshot {
+                let path = image::timestamped_path(&self.screenshot_dir, "screenshot", "png", SystemTime::now());
+                let (size, pixels) = self.read_framebuffer();
+                image::save_png(&path, size.0, size.1, &pixels)?;
+                println!("Saved {}", path.display());
+                take_screenshot = false;
+            }
+
+            if toggle_recording {
+                match recorder.take() {
+                    Some(recording) => finish_recording(recording)?,
+                    None => {
+                        let (width, height) = self.window.get_framebuffer_size();
+                        let extension = self.record_format.extension();
+                        let path = image::timestamped_path(&self.screenshot_dir, "recording", extension, SystemTime::now());
+                        let size = (width as usize, height as usize);
+                        recorder = Some(Recorder::create(&path, self.record_format, size, self.record)?);
+                        println!("Recording to {}", path.display());
+                    }
+                }
+                toggle_recording = false;
+            }
+
+            if let Some(recording) = recorder.as_mut().filter(|recording| recording.wants(tick_count)) {
+                let (size, pixels) = self.read_framebuffer();
+
+                // Every frame of a recording has the same size
+                if size == recording.frame_size() {
+                    recording.frame(tick_count, &pixels)?;
+                } else {
+                    println!("The window was resized, the recording stops");
+                    toggle_recording = true;
+                }
+            }
+            if let Some(recording) = recorder.take_if(|recording| recording.is_full()) {
+                finish_recording(recording)?;
+            }
+
             self.window.swap_buffers();
         }
 
+        if let Some(recording) = recorder {
+            finish_recording(recording)?;
+        }
+
         Ok(())
     }
 
-    fn generate_map(field_size: &Vec2<i32>) -> Box<Vec<Cell>> {
-        let mut grid = Box::new(Vec::new());
+    /// Draws an image of `0xRRGGBB` pixels over the top left corner of the window,
+    /// or over the bottom left one if `at_bottom` is set. Used for the HUD and the legend.
+    fn draw_overlay(&mut self, image: &[u32], (width, height): (usize, usize), at_bottom: bool) {
+        let (framebuffer_width, framebuffer_height) = self.window.get_framebuffer_size();
+        let (window_width, _) = self.window.get_size();
+        let scale = HUD_SCALE * (framebuffer_width / window_width.max(1)).max(1);
+        let top = if at_bottom { framebuffer_height - (height as i32) * scale } else { 0 };
+
+        // The buffer is sized for the HUD, smaller images leave the rest unused
+        let mut pixels = image.to_vec();
+        pixels.resize(hud::WIDTH * hud::HEIGHT, 0);
+        self.hud_sb.map_data(&pixels);
+
+        self.gl_ctx.set_viewport(0, 0, framebuffer_width, framebuffer_height);
+        self.gl_ctx.bind_pipeline(&self.hud_program);
+        self.hud_program.set_uniform("u_framebuffer_size", Uniform::Vec2(framebuffer_width as f32, framebuffer_height as f32));
+        self.hud_program.set_uniform("u_hud_origin", Uniform::Vec2(0.0, top as f32));
+        self.hud_program.set_uniform("u_hud_size", Uniform::Vec2(width as f32, height as f32));
+        self.hud_program.set_uniform("u_hud_scale", Uniform::Float(scale as f32));
+        self.hud_program.bind_storage_buffer(self.hud_sb.get_id(), 4);
+
+        self.quad.draw();
+    }
 
-        // New empty grid
-        for _ in 0..field_size.x * field_size.y {
-            grid.push(Cell {
-                element_type: CellType::Empty as i32,
-                mass: 0.0,
-            });
+    /// The back buffer, HUD included, and its size
+    fn read_framebuffer(&self) -> ((usize, usize), Vec<u32>) {
+        let (width, height) = self.window.get_framebuffer_size();
+        let (width, height) = (width as usize, height as usize);
+        let mut rgba = vec![0u8; width * height * 4];
+
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadBuffer(gl::BACK);
+            gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, rgba.as_mut_ptr() as *mut _);
         }
 
-        // Initial Test Case
-        for i in 100..190 {
-            grid[i as usize + (128 * field_size.x as usize)] = Cell { element_type: CellType::Block as i32, mass: 0.0 };
-            grid[i as usize + (129 * field_size.x as usize)] = Cell { element_type: CellType::Block as i32, mass: 0.0 };
-            grid[i as usize + (130 * field_size.x as usize)] = Cell { element_type: CellType::Block as i32, mass: 0.0 };
+        // GL returns the bottom row first
+        let pixels: Vec<u32> = rgba
+            .chunks(width * 4)
+            .rev()
+            .flat_map(|row| row.chunks(4).map(|p| (p[0] as u32) << 16 | (p[1] as u32) << 8 | p[2] as u32))
+            .collect();
+
+        ((width, height), pixels)
+    }
+
+    /// Runs one generation of the compute shader, its uniforms have to be set already
+    fn dispatch(&mut self) {
+        self.compute_program.bind_storage_buffer(self.prev_sb.get_id(), 0);
+        self.compute_program.bind_storage_buffer(self.curr_sb.get_id(), 1);
+        self.compute_program.bind_storage_buffer(self.tmp_sb.get_id(), 2);
+        self.compute_program.bind_storage_buffer(self.emitters_sb.get_id(), 3);
+
+        // Round up, the shader skips the invocations that fall outside of the field
+        self.gl_ctx.dispatch_compute(
+            (self.field_size.x as u32).div_ceil(8),
+            (self.field_size.y as u32).div_ceil(8),
+            1,
+        );
+
+        // FENCE and sync
+
+        self.gl_ctx.memory_barrier(MemoryBarrier::ShaderStorage);
+
+        swap(&mut self.curr_sb, &mut self.prev_sb);
+    }
+
+    /// Runs `ticks` generations without rendering, timing every dispatch with a timer query
+    fn measure(&mut self, ticks: u64) -> Duration {
+        self.gl_ctx.bind_pipeline(&self.compute_program);
+
+        self.compute_program.set_uniform("u_resolution", Uniform::Vec2(self.field_size.x as f32, self.field_size.y as f32));
+        self.compute_program.set_uniform("u_drawing", Uniform::Int(0));
+        self.compute_program.set_uniform("u_rotation_signal", Uniform::Int(0));
+        self.compute_program.set_uniform("u_emitter_count", Uniform::Int(self.emitter_count as i32));
+        self.set_params_uniforms();
+
+        let mut queries = vec![0u32; ticks as usize];
+        unsafe { gl::GenQueries(queries.len() as i32, queries.as_mut_ptr()); }
+
+        for &query in &queries {
+            unsafe { gl::BeginQuery(gl::TIME_ELAPSED, query); }
+            self.dispatch();
+            unsafe { gl::EndQuery(gl::TIME_ELAPSED); }
         }
 
-        for i in 0..5 {
-            for j in 0..5 {
-                if i == j {
-                    grid[(128 + i) + (190 + j) * 256] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
-                }
-            }
+        // Reading a result waits for its dispatch to finish
+        let mut total_ns = 0;
+        for &query in &queries {
+            let mut elapsed_ns = 0u64;
+            unsafe { gl::GetQueryObjectui64v(query, gl::QUERY_RESULT, &mut elapsed_ns); }
+            total_ns += elapsed_ns;
         }
 
-        grid[129 + 190 * 256] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
-        grid[129 + 200 * 256] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
-        grid[129 + 210 * 256] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
-        grid[129 + 180 * 256] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
-        grid[129 + 170 * 256] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
-        grid[129 + 160 * 256] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
-        grid[129 + 120 * 256] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
-        grid[132 + 190 * 256] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
-        grid[126 + 196 * 256] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
+        unsafe { gl::DeleteQueries(queries.len() as i32, queries.as_ptr()); }
 
-        grid
+        Duration::from_nanos(total_ns)
     }
 
-    fn initialize_cave() -> [[bool; WIDTH]; HEIGHT] {
-        let mut cave_map = [[false; WIDTH]; HEIGHT];
-        let chance_to_start_alive = 0.38;
+    /// Paints cells on the CPU and uploads the whole field again. Shapes are only
+    /// painted when a drag ends, so the round trip does not hold up the simulation.
+    fn paint_cells(&mut self, cells: &[(i32, i32)], element_type: i32) {
+        let mut field = self.read_cells();
+        let mut mass = self.read_mass();
 
-        for i in 0..WIDTH {
-            for j in 0..HEIGHT {
-                let chance: f64 = rand::thread_rng().gen();
-                if chance < chance_to_start_alive {
-                    cave_map[i][j] = true;
-                }
-            }
+        let painted = Cell {
+            element_type,
+            mass: if element_type == CellType::Water as i32 { self.params.max_mass } else { 0.0 },
+        };
+
+        for &cell in cells {
+            let idx = match cell_index(&self.field_size, cell) {
+                Some(idx) => idx,
+                None => continue,
+            };
+
+            field[idx] = painted;
+            mass[idx] = painted.mass;
         }
 
-        cave_map
+        self.prev_sb.map_data(&field);
+        self.tmp_sb.map_data(&mass);
+    }
+
+    fn flood_fill(&mut self, (x, y): (i32, i32), element_type: i32) {
+        let (width, height) = (self.field_size.x, self.field_size.y);
+        let start = match cell_index(&self.field_size, (x, y)) {
+            Some(start) => start,
+            None => return,
+        };
+
+        let field = self.read_cells();
+        let target = field[start].element_type;
+        let region = tools::flood_fill(width as usize, height as usize, (x as usize, y as usize), |x, y| {
+            field[x + y * width as usize].element_type == target
+        });
+        let cells: Vec<(i32, i32)> = region.into_iter().map(|(x, y)| (x as i32, y as i32)).collect();
+
+        self.paint_cells(&cells, element_type);
     }
 
-    fn count_neighbours(map: [[bool; WIDTH]; HEIGHT], x: usize, y: usize) -> i32 {
-        let mut count_n = 0;
+    /// Reads a single cell back from the GPU, `None` outside of the field
+    fn cell_at(&self, position: (i32, i32)) -> Option<Cell> {
+        let idx = cell_index(&self.field_size, position)?;
+        let mut cell = Cell::default();
+        let size = std::mem::size_of::<Cell>();
+
+        unsafe {
+            gl::GetNamedBufferSubData(
+                self.prev_sb.get_id(),
+                (idx * size) as isize,
+                size as isize,
+                &mut cell as *mut Cell as *mut std::os::raw::c_void,
+            );
+        }
 
-        for ii in 0..3 {
-            for jj in 0..3 {
-                let i = ii as i32 - 1;
-                let j = jj as i32 - 1;
+        Some(cell)
+    }
 
-                if i == 0 && j == 0 {
-                    continue;
-                }
+    /// Copies the current generation back from the GPU
+    fn read_cells(&self) -> Vec<Cell> {
+        let mut cells = vec![Cell::default(); (self.field_size.x * self.field_size.y) as usize];
+
+        unsafe {
+            gl::GetNamedBufferSubData(
+                self.prev_sb.get_id(),
+                0,
+                (cells.len() * std::mem::size_of::<Cell>()) as isize,
+                cells.as_mut_ptr() as *mut std::os::raw::c_void,
+            );
+        }
 
-                let n_x = x as i32 + i;
-                let n_y = y as i32 + j;
+        cells
+    }
 
-                if n_x < 0 || n_y < 0 || n_x >= WIDTH as i32 || n_y >= HEIGHT as i32 {
-                    count_n += 1;
-                } else if map[n_x as usize][n_y as usize] {
-                    count_n += 1;
-                }
-            }
+    /// Copies the mass of every cell back from the GPU
+    fn read_mass(&self) -> Vec<f32> {
+        let mut mass = vec![0.0f32; (self.field_size.x * self.field_size.y) as usize];
+
+        unsafe {
+            gl::GetNamedBufferSubData(
+                self.tmp_sb.get_id(),
+                0,
+                (mass.len() * std::mem::size_of::<f32>()) as isize,
+                mass.as_mut_ptr() as *mut std::os::raw::c_void,
+            );
         }
 
-        count_n
+        mass
     }
 
-    fn do_cave_generation_step(old_map: [[bool; WIDTH]; HEIGHT]) -> [[bool; WIDTH]; HEIGHT] {
-        let mut new_map = [[false; WIDTH]; HEIGHT];
-        let death_limit = 3;
-        let birth_limit = 3;
-
-        for i in 0..WIDTH {
-            for j in 0..HEIGHT {
-                let nbs = Application::count_neighbours(old_map, i, j);
-                if old_map[i][j] {
-                    if nbs < death_limit {
-                        new_map[i][j] = false;
-                    } else {
-                        new_map[i][j] = true;
-                    }
-                } else {
-                    if nbs > birth_limit {
-                        new_map[i][j] = true;
-                    } else {
-                        new_map[i][j] = false;
-                    }
+    fn set_params_uniforms(&self) {
+        self.compute_program.set_uniform("u_max_mass", Uniform::Float(self.params.max_mass));
+        self.compute_program.set_uniform("u_max_compression", Uniform::Float(self.params.max_compress));
+        self.compute_program.set_uniform("u_min_mass", Uniform::Float(self.params.min_mass));
+        self.compute_program.set_uniform("u_min_flow", Uniform::Float(self.params.min_flow));
+        self.compute_program.set_uniform("u_max_flow", Uniform::Float(self.params.max_speed));
+        self.compute_program.set_uniform("u_flow_speed", Uniform::Float(self.params.flow_speed));
+    }
+
+    fn generate_map(field_size: &Vec2<i32>) -> Box<Vec<Cell>> {
+        let mut grid = Box::new(Vec::new());
+
+        // New empty grid
+        for _ in 0..field_size.x * field_size.y {
+            grid.push(Cell {
+                element_type: CellType::Empty as i32,
+                mass: 0.0,
+            });
+        }
+
+        // The test case is laid out for the default field size
+        if field_size.x < FIELD_WIDTH || field_size.y < FIELD_HEIGHT {
+            return grid;
+        }
+
+        // Initial Test Case
+        for i in 100..190 {
+            grid[i as usize + (128 * field_size.x as usize)] = Cell { element_type: CellType::Block as i32, mass: 0.0 };
+            grid[i as usize + (129 * field_size.x as usize)] = Cell { element_type: CellType::Block as i32, mass: 0.0 };
+            grid[i as usize + (130 * field_size.x as usize)] = Cell { element_type: CellType::Block as i32, mass: 0.0 };
+        }
+
+        for i in 0..5 {
+            for j in 0..5 {
+                if i == j {
+                    grid[(128 + i) + (190 + j) * field_size.x as usize] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
                 }
             }
         }
 
-        new_map
-    }
+        grid[129 + 190 * field_size.x as usize] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
+        grid[129 + 200 * field_size.x as usize] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
+        grid[129 + 210 * field_size.x as usize] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
+        grid[129 + 180 * field_size.x as usize] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
+        grid[129 + 170 * field_size.x as usize] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
+        grid[129 + 160 * field_size.x as usize] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
+        grid[129 + 120 * field_size.x as usize] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
+        grid[132 + 190 * field_size.x as usize] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
+        grid[126 + 196 * field_size.x as usize] = Cell { element_type: CellType::Water as i32, mass: 1.0 };
 
-    fn generate_cave() -> Vec<Cell> {
-        let mut grid = vec![Cell::default(); WIDTH * HEIGHT];
-        let mut cave_map = Application::initialize_cave();
+        grid
+    }
 
-        for _ in 0..4 {
-            cave_map = Application::do_cave_generation_step(cave_map);
-        }
+    fn generate_cave(field_size: &Vec2<i32>, rng: &mut XorShiftRng) -> Vec<Cell> {
+        let (width, height) = (field_size.x as usize, field_size.y as usize);
+        let mut grid = vec![Cell::default(); width * height];
+        let cave_map = cave::generate(width, height, &CaveSettings::gpu(), rng);
 
-        for i in 0..WIDTH {
-            for j in 0..HEIGHT {
-                let idx = (i + j * WIDTH as usize);
-                if cave_map[i][j] {
+        for (i, column) in cave_map.iter().enumerate() {
+            for (j, &open) in column.iter().enumerate() {
+                let idx = i + j * width;
+                if open {
                     grid[idx] = Cell {
                         element_type: CellType::Empty as i32,
                         mass: 0.0,
@@ -479,9 +1117,49 @@ impl Application {
     }
 }
 
-pub fn run_simulation() -> Result<(), Box<dyn Error + 'static>> {
-    let mut app = Application::new()?;
+fn finish_recording(recorder: Recorder) -> Result<(), Box<dyn Error>> {
+    let (frames, path) = (recorder.frames(), recorder.path().to_path_buf());
+    recorder.finish()?;
+    println!("Saved {} frames to {}", frames, path.display());
+
+    Ok(())
+}
+
+pub fn run_simulation(config: Config) -> Result<(), Box<dyn Error + 'static>> {
+    let mut app = Application::new(config)?;
     app.run()?;
 
     Ok(())
 }
+
+/// Times `ticks` compute dispatches of `scenario` in a hidden window
+pub fn measure_gpu(scenario: Scenario, ticks: u64) -> Result<Measurement, Box<dyn Error>> {
+    let (width, height) = (scenario.width, scenario.height);
+    let config = Config {
+        scenario: Some(scenario),
+        visible: false,
+        ..Config::default()
+    };
+
+    let mut app = Application::new(config)?;
+    let total_time = app.measure(ticks);
+
+    Ok(Measurement {
+        backend: Backend::Gpu,
+        width,
+        height,
+        ticks,
+        total_time,
+    })
+}
+
+#[test]
+fn test_cell_index_clips_to_field() {
+    let field_size = Vec2 { x: 4, y: 3 };
+
+    assert_eq!(cell_index(&field_size, (0, 0)), Some(0));
+    assert_eq!(cell_index(&field_size, (3, 2)), Some(11));
+    for &outside in &[(-1, 0), (0, -1), (4, 0), (0, 3), (4, 3), (-1, -1)] {
+        assert_eq!(cell_index(&field_size, outside), None);
+    }
+}
@@ -1,9 +1,3 @@
-use std::fs::File;
-use std::io::{BufReader, BufRead, Read};
-use std::path::Path;
-use rand::Rng;
-use std::time::Instant;
-
 use minifb::{
     Key,
     WindowOptions,
@@ -13,615 +7,537 @@ use minifb::{
     KeyRepeat,
     CursorStyle,
 };
-use vek::Vec2;
-use std::ops::Sub;
+
+use std::error::Error;
+use std::process;
+use std::time::{Duration, Instant, SystemTime};
+
+use automata_sandbox::ascii::Grid;
+use automata_sandbox::camera::{self, Camera};
+use automata_sandbox::compare;
+use automata_sandbox::headless::{self, RunOptions};
+use automata_sandbox::hud::{self, FpsCounter, Hud};
+use automata_sandbox::image;
+use automata_sandbox::palette;
+use automata_sandbox::record::Recorder;
+use automata_sandbox::scenario::Scenario;
+use automata_sandbox::simulation;
+use automata_sandbox::stats::{Stats, StatsWriter};
+use automata_sandbox::stepping::{self, Stepper};
+use automata_sandbox::tools::{self, BrushMode, Tool};
+use automata_sandbox::validation;
+use automata_sandbox::views::{self, View};
+use automata_sandbox::world::{Cell, World};
+use automata_sandbox::{Backend, Config};
+
+mod cli;
+
+use cli::Options;
 
 const WIDTH: usize = 300;
 const HEIGHT: usize = 300;
 
 const FRAME_DELAY: u64 = 0;
-const MIN_FLOW: f32 = 0.01;
-const MAX_MASS: f32 = 10.0;
-const MAX_COMPRESS: f32 = 0.02;
-const MIN_MASS: f32 = 0.0001;
-const MIN_DRAW: f32 = 0.01;
-const MAX_DRAW: f32 = 1.1;
-const MAX_SPEED: f32 = 1.0;
-
-#[derive(Clone, PartialEq)]
-enum Color {
-    Black,
-    White,
-    Red,
-    Yellow,
-    Green,
-    Cyan,
-    Purple,
-    Blue,
-    Desert,
-}
 
-impl Color {
-    pub fn get_hex(&self) -> u32 {
-        use Color::*;
-
-        match self {
-            Black => 0x000000,
-            White => 0xffffff,
-            Red => 0xff0000,
-            Yellow => 0xffff00,
-            Green => 0x00ff00,
-            Cyan => 0x00ffff,
-            Blue => 0x0000ff,
-            Purple => 0xff00ff,
-            Desert => 0xccae62,
-        }
-    }
-}
+/// Largest side of the CPU window in cells, larger worlds are shown through the camera
+const MAX_VIEW: usize = 640;
 
-#[derive(Copy, Clone, PartialOrd, PartialEq, Debug)]
-enum Cell {
-    Water,
-    Ground,
-    Air,
-}
-
-impl Cell {
-    fn empty() -> Self {
-        Cell::Air
+/// Size of the CPU window's buffer for a world, scaled down to fit `MAX_VIEW`
+fn view_size(width: usize, height: usize) -> (usize, usize) {
+    let longest = width.max(height);
+    if longest <= MAX_VIEW {
+        return (width, height);
     }
-}
 
-#[derive(Copy, Clone)]
-struct Widget {
-    active: bool,
-    element: Cell,
+    ((width * MAX_VIEW / longest).max(1), (height * MAX_VIEW / longest).max(1))
 }
 
-impl Widget {
-    fn new(element: Cell) -> Self {
-        Widget {
-            active: false,
-            element,
-        }
-    }
+/// Builds the CPU world from a saved grid, a scenario or an empty canvas, in that order
+fn load_world(options: &Options) -> Result<(World, Option<u64>), Box<dyn Error>> {
+    if let Some(path) = &options.load {
+        let grid: Grid = std::fs::read_to_string(path)?.parse()?;
 
-    fn toggle(&mut self) {
-        self.active = !self.active;
+        return Ok((grid.to_world()?, options.ticks));
     }
 
-    fn get_color(&self) -> Color {
-        match self.active {
-            true => Color::Red,
-            false => Color::Yellow,
+    if let Some(path) = &options.scenario {
+        let mut scenario = Scenario::load(path)?;
+        if options.seed.is_some() {
+            scenario.seed = options.seed;
         }
-    }
-}
-
-struct World {
-    water: Box<Vec<i32>>,
-    energy: Box<Vec<i32>>,
-    ground: Box<Vec<i32>>,
 
-    mass: Box<Vec<Vec<f32>>>,
-    new_mass: Box<Vec<Vec<f32>>>,
-    blocks: Box<Vec<Vec<Cell>>>,
-
-    widgets: Vec<Widget>,
-    selected_element: Cell,
-}
+        return Ok((scenario.to_world()?, options.ticks.or(scenario.ticks)));
+    }
 
-pub fn clamp<T: PartialOrd>(val: T, min: T, max: T) -> T {
-    if val < min {
-        min
-    } else {
-        if val > max {
-            max
-        } else {
-            val
-        }
+    let (width, height) = options.size.unwrap_or((WIDTH, HEIGHT));
+    let mut world = World::new(width, height);
+    if let Some(seed) = options.seed {
+        world.reseed(seed);
     }
-}
 
-pub fn lerp_range(x: f32, in_min: f32, in_max: f32, out_min: f32, out_max: f32) -> f32 {
-    (x - in_min) * (out_max - out_min) / (in_max - in_min) + out_min
+    Ok((world, options.ticks))
 }
 
-#[test]
-fn test_lerp() {
-    assert_eq!(lerp_range(5.0, 0.0, 10.0, 0.0, 100.0), 50.0);
+fn window_scale(scale: u32) -> Result<minifb::Scale, Box<dyn Error>> {
+    Ok(match scale {
+        1 => minifb::Scale::X1,
+        2 => minifb::Scale::X2,
+        4 => minifb::Scale::X4,
+        8 => minifb::Scale::X8,
+        16 => minifb::Scale::X16,
+        32 => minifb::Scale::X32,
+        _ => return Err(format!("the cpu window can only be scaled by a power of two up to 32, not {}", scale).into()),
+    })
 }
 
-impl World {
-    fn new() -> Self {
-        let mut this = Self {
-            water: Box::new(vec![0; WIDTH]),
-            energy: Box::new(vec![0; WIDTH]),
-            ground: Box::new(vec![0; WIDTH]),
-
-            mass: Box::new(vec![vec![0.0; WIDTH]; HEIGHT]),
-            new_mass: Box::new(vec![vec![0.0; WIDTH]; HEIGHT]),
-            blocks: Box::new(vec![vec![Cell::empty(); WIDTH]; HEIGHT]),
+fn headless(options: &Options) -> Result<(), Box<dyn Error>> {
+    let (mut simulation, tick_limit) = match (&options.load, &options.scenario) {
+        (Some(path), _) => {
+            let grid: Grid = std::fs::read_to_string(path)?.parse()?;
+            (simulation::from_grid(options.backend, &grid)?, options.ticks)
+        }
+        (None, Some(path)) => {
+            let mut scenario = Scenario::load(path)?;
+            if options.seed.is_some() {
+                scenario.seed = options.seed;
+            }
+            (simulation::from_scenario(options.backend, &scenario)?, options.ticks.or(scenario.ticks))
+        }
+        (None, None) => return Err("headless mode needs --load or --scenario".into()),
+    };
+
+    let run_options = RunOptions {
+        ticks: tick_limit.ok_or("headless mode needs --ticks or a scenario with a tick count")?,
+        snapshot_every: options.snapshot_every,
+        output_dir: options.output_dir.clone(),
+        stats_path: options.stats.clone(),
+        image_scale: options.images,
+        recording: options.recording.clone(),
+        record: options.record,
+    };
+
+    let summary = headless::run(simulation.as_mut(), &run_options)?;
+
+    println!(
+        "{} ticks in {:?}, total mass {} -> {}",
+        summary.ticks, summary.elapsed, summary.initial_mass, summary.final_mass,
+    );
+
+    Ok(())
+}
 
-            widgets: Vec::new(),
-            selected_element: Cell::Ground,
+/// Default ticks per measurement when neither `--ticks` nor the scenario set them
+const COMPARE_TICKS: u64 = 100;
+
+fn compare(options: &Options, sizes: &[(usize, usize)]) -> Result<(), Box<dyn Error>> {
+    let scenario = match &options.scenario {
+        Some(path) => Some(Scenario::load(path)?),
+        None => None,
+    };
+    let ticks = options
+        .ticks
+        .or_else(|| scenario.as_ref().and_then(|scenario| scenario.ticks))
+        .unwrap_or(COMPARE_TICKS);
+
+    let mut measurements = Vec::new();
+
+    for &(width, height) in sizes {
+        let mut scenario = match &scenario {
+            Some(scenario) => compare::resize(scenario, width, height),
+            None => compare::default_scenario(width, height),
         };
+        if options.seed.is_some() {
+            scenario.seed = options.seed;
+        }
 
-        this.widgets.push(Widget::new(Cell::Ground));
-        this.widgets.push(Widget::new(Cell::Water));
-        this.select_element(Cell::Ground);
-
-        this
-    }
-
-    fn get_stable_state(&self, total_mass: f32) -> f32 {
-        if total_mass <= 1.0 {
-            1.0
-        } else if total_mass < 2.0 * MAX_MASS + MAX_COMPRESS {
-            (MAX_MASS.powi(2) + total_mass * MAX_COMPRESS) / (MAX_MASS + MAX_COMPRESS)
-        } else {
-            (total_mass + MAX_COMPRESS) / 2.0
+        for measurement in [compare::measure_cpu(&scenario, ticks)?, automata_sandbox::measure_gpu(scenario, ticks)?] {
+            println!(
+                "{} {}x{}: {:?} per tick, {:.1} ticks/s",
+                measurement.backend, width, height, measurement.tick_time(), measurement.ticks_per_second(),
+            );
+            measurements.push(measurement);
         }
     }
 
-    fn clear_map(&mut self) {
-        self.water = Box::new(vec![0; WIDTH]);
-        self.energy = Box::new(vec![0; WIDTH]);
-        self.ground = Box::new(vec![0; WIDTH]);
-        self.mass = Box::new(vec![vec![0.0; WIDTH]; HEIGHT]);
-        self.new_mass = Box::new(vec![vec![0.0; WIDTH]; HEIGHT]);
-        self.blocks = Box::new(vec![vec![Cell::empty(); WIDTH]; HEIGHT]);
-    }
+    std::fs::create_dir_all(&options.output_dir)?;
+    compare::write_csv(options.output_dir.join("compare.csv"), &measurements)
+}
 
-    fn tick(&mut self) {
-        let mut flow = 0.0;
-        let mut blocks = self.blocks.clone();
-        let mass = self.mass.clone();
-        let mut new_mass = self.new_mass.clone();
-        // let mut new_mass = [[0.0; WIDTH]; HEIGHT];
-        let mut remaining_mass;
-
-        // Calculate and apply flow for each block
-        for x in 0..WIDTH - 1 {
-            for y in 0..HEIGHT - 1 {
-                // Skip inert ground blocks
-                if blocks[x][y] == Cell::Ground {
-                    continue;
-                }
+fn validate() -> Result<(), Box<dyn Error>> {
+    let settled = |ticks: Option<u64>| ticks.map_or("never".to_string(), |ticks| ticks.to_string());
 
-                // Custom push-only flow
-                flow = 0.0;
-                remaining_mass = mass[x][y];
+    println!("U-tube, level difference between the arms in cells (ideal 0)");
+    println!("{:<10} {:<8} {:>8} {:>8} {:>10} {:>8}", "preset", "backend", "left", "right", "difference", "settled");
 
-                if remaining_mass <= 0.0 {
-                    continue;
-                }
+    let reports = validation::run()?;
 
-                // The block below this one
-                if blocks[x][y + 1] != Cell::Ground {
-                    flow = self.get_stable_state(remaining_mass + mass[x][y + 1]) - mass[x][y + 1];
-                    if flow > MIN_FLOW {
-                        flow *= 0.8; // leads to smoother flow
-                    }
+    for r in &reports {
+        println!(
+            "{:<10} {:<8} {:>8.2} {:>8.2} {:>10.3} {:>8}",
+            r.preset, r.backend, r.u_tube.left_level, r.u_tube.right_level, r.u_tube.level_difference(),
+            settled(r.u_tube.settled_after),
+        );
+    }
 
-                    flow = clamp(flow, 0.0, remaining_mass.min(MAX_SPEED));
+    println!();
+    println!("Column, mass per cell against depth in units of max_mass (ideal slope max_compress / max_mass)");
+    println!("{:<10} {:<8} {:>6} {:>8} {:>8} {:>10} {:>8}", "preset", "backend", "rows", "slope", "ideal", "max error", "settled");
 
-                    new_mass[x][y] -= flow;
-                    new_mass[x][y + 1] += flow;
-                    remaining_mass -= flow;
-                }
+    for r in &reports {
+        println!(
+            "{:<10} {:<8} {:>6} {:>8.4} {:>8.4} {:>10.3} {:>8}",
+            r.preset, r.backend, r.column.masses.len(), r.column.slope(), r.column.ideal_slope, r.column.max_error(),
+            settled(r.column.settled_after),
+        );
+    }
 
-                if remaining_mass <= 0.0 {
-                    continue;
-                }
+    Ok(())
+}
 
-                // Left
-                if blocks[x - 1][y] != Cell::Ground {
-                    // Equalize the amount of water in this block and its neighbor
-                    flow = (mass[x][y] - mass[x - 1][y]) / 4.0;
-                    if flow > MIN_FLOW {
-                        flow *= 0.8;
-                    }
-                    flow = clamp(flow, 0.0, remaining_mass);
-
-                    new_mass[x][y] -= flow;
-                    new_mass[x - 1][y] += flow;
-                    remaining_mass -= flow;
-                }
+fn cpu_rendering(options: &Options) -> Result<(), Box<dyn Error>> {
+    let (mut world, tick_limit) = load_world(options)?;
+    let (width, height) = (world.width(), world.height());
+    let mut tick_count = 0;
+
+    let mut stats = match &options.stats {
+        Some(path) => Some(StatsWriter::create(path)?),
+        None => None,
+    };
+
+    // The world is rendered into the frame, the camera picks what of it ends up in the window
+    let mut frame = vec![0; width * height];
+    let (view_width, view_height) = view_size(width, height);
+    let mut buff = vec![0; view_width * view_height];
+    let mut camera = Camera::new(width, height);
+    let mut window = Window::new(
+        "CA Water Simulation",
+        view_width,
+        view_height,
+        WindowOptions {
+            scale: window_scale(options.scale)?,
+            ..WindowOptions::default()
+        },
+    )?;
 
-                if remaining_mass <= 0.0 {
-                    continue;
-                }
+    let frame_delay = match options.update_rate {
+        Some(rate) => Duration::from_secs_f64(1.0 / rate),
+        None => Duration::from_micros(FRAME_DELAY),
+    };
 
-                // Right
-                if blocks[x + 1][y] != Cell::Ground {
-                    flow = (mass[x][y] - mass[x + 1][y]) / 4.0;
-                    if flow > MIN_FLOW {
-                        flow *= 0.8;
-                    }
+    window.limit_update_rate(Some(frame_delay));
+    window.set_cursor_style(CursorStyle::Crosshair);
 
-                    flow = clamp(flow, 0.0, remaining_mass);
+    let mut tool = Tool::Brush;
+    let mut brush_mode = BrushMode::Paint;
+    let mut flow_rate = options.flow_rate;
+    let mut radius = 2.0;
+    let mut drag_start = None;
+    let mut last_cursor = (0, 0);
+    let mut was_down = false;
+    let mut was_picking = false;
+    // Set while a button that went down over the palette is held, so the click does not paint
+    let mut on_palette = false;
+    // The right button erases with whichever tool is selected
+    let mut erasing = false;
+
+    let mut stepper = Stepper::new(options.ticks_per_frame);
+    let mut show_hud = true;
+    let mut view = View::Normal;
+    let mut smooth_surface = false;
+    let mut take_screenshot = false;
+    let mut toggle_recording = false;
+    let mut recorder: Option<Recorder> = None;
+    let mut fps = FpsCounter::new();
+    let mut tps = FpsCounter::new();
+    let mut tick_time = Duration::from_secs(0);
 
-                    new_mass[x][y] -= flow;
-                    new_mass[x + 1][y] += flow;
-                    remaining_mass -= flow;
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        let shift = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        window.get_keys_pressed(KeyRepeat::No).map(|keys| {
+            for t in keys {
+                match t {
+                    Key::Key1 => world.select_element(Cell::Ground),
+                    Key::Key2 => world.select_element(Cell::Water),
+                    Key::E => world.rotate_canvas_anticlockwise(),
+                    Key::R => world.rotate_canvas_clockwise(),
+                    Key::N => world.generate_map(),
+                    Key::C => world.clear_map(),
+                    Key::P => stepper.toggle_pause(),
+                    Key::Period if shift => stepper.step(stepping::STEP_COUNT),
+                    Key::Period => stepper.step(1),
+                    Key::PageUp if shift => stepper.more_ticks_per_frame(),
+                    Key::PageDown if shift => stepper.fewer_ticks_per_frame(),
+                    Key::PageUp => stepper.faster(),
+                    Key::PageDown => stepper.slower(),
+                    Key::F => stepper.toggle_fast_forward(),
+                    Key::H => show_hud = !show_hud,
+                    Key::V => view = view.next(),
+                    Key::W => smooth_surface = !smooth_surface,
+                    Key::F12 => take_screenshot = true,
+                    Key::F10 => toggle_recording = true,
+                    Key::Left => camera.pan((-camera::PAN_STEP, 0.0)),
+                    Key::Right => camera.pan((camera::PAN_STEP, 0.0)),
+                    // The world's y axis points down
+                    Key::Up => camera.pan((0.0, -camera::PAN_STEP)),
+                    Key::Down => camera.pan((0.0, camera::PAN_STEP)),
+                    Key::Key0 => camera.reset(),
+                    Key::B => tool = Tool::Brush,
+                    Key::L => tool = Tool::Line,
+                    Key::O => tool = Tool::Rect,
+                    Key::S => tool = Tool::FilledRect,
+                    Key::U => tool = Tool::Ellipse,
+                    Key::G => tool = Tool::Fill,
+                    Key::X => tool = Tool::Eraser,
+                    Key::M => brush_mode = brush_mode.next(),
+                    Key::Minus => flow_rate = (flow_rate / 2.0).clamp(tools::MIN_FLOW_RATE, tools::MAX_FLOW_RATE),
+                    Key::Equal => flow_rate = (flow_rate * 2.0).clamp(tools::MIN_FLOW_RATE, tools::MAX_FLOW_RATE),
+                    Key::LeftBracket => radius = (radius - 1.0f32).clamp(tools::MIN_RADIUS, tools::MAX_RADIUS),
+                    Key::RightBracket => radius = (radius + 1.0f32).clamp(tools::MIN_RADIUS, tools::MAX_RADIUS),
+                    _ => (),
                 }
+            }
+        }).unwrap();
 
-                if remaining_mass <= 0.0 {
-                    continue;
+        if let Some((_, scroll)) = window.get_scroll_wheel() {
+            if window.is_key_down(Key::LeftCtrl) || window.is_key_down(Key::RightCtrl) {
+                if let Some((x, y)) = window.get_mouse_pos(MouseMode::Clamp) {
+                    let anchor = (x / view_width as f32, y / view_height as f32);
+                    camera.zoom_at(camera::ZOOM_STEP.powf(scroll.signum()), anchor);
                 }
+            } else {
+                radius = (radius + scroll.signum()).clamp(tools::MIN_RADIUS, tools::MAX_RADIUS);
+            }
+        }
 
-                // Up. Only compressed water flows upwards
-                if blocks[x][y - 1] != Cell::Ground {
-                    flow = remaining_mass - self.get_stable_state(remaining_mass + mass[x][y - 1]);
-                    if flow >= MIN_FLOW {
-                        flow *= 0.8;
-                    }
-
-                    flow = clamp(flow, 0.0, remaining_mass.min(MAX_SPEED));
+        // Cells the brush pours into or drains on every tick of this frame
+        let mut flow_cells = Vec::new();
+
+        // Clamp, so a shape dragged off the window still ends on its edge
+        if let Some((x, y)) = window.get_mouse_pos(MouseMode::Clamp) {
+            let cursor = camera.to_cell((x / view_width as f32, y / view_height as f32));
+            let left_down = window.get_mouse_down(MouseButton::Left);
+            let right_down = window.get_mouse_down(MouseButton::Right);
+            let is_down = left_down || right_down;
+            if is_down && !was_down {
+                erasing = right_down && !left_down;
+            }
 
-                    new_mass[x][y] -= flow;
-                    new_mass[x][y - 1] += flow;
-                    remaining_mass -= flow;
+            // The palette is drawn over the view, not the field
+            let (view_x, view_y) = (x as usize, y as usize);
+            if is_down && !was_down && show_hud && palette::contains(view_x, view_y) {
+                on_palette = true;
+                if let Some(action) = palette::hit(view_x, view_y) {
+                    action.apply(&mut world);
                 }
+            } else if !is_down {
+                on_palette = false;
             }
-        }
 
-        for x in 0..WIDTH {
-            for y in 0..HEIGHT {
-                // Skip ground blocks
-                if blocks[x][y] == Cell::Ground {
-                    continue;
-                }
+            let element = if tool == Tool::Eraser || erasing { Cell::Air } else { world.selected_element() };
 
-                // Flag/unflag water blocks
-                if mass[x][y] > MIN_MASS {
-                    blocks[x][y] = Cell::Water;
-                } else {
-                    blocks[x][y] = Cell::Air;
+            // Eyedropper
+            let is_picking = window.get_mouse_down(MouseButton::Middle);
+            if is_picking && !was_picking {
+                world.select_element(world.cell(cursor.0 as usize, cursor.1 as usize));
+            }
+            was_picking = is_picking;
+
+            if on_palette {
+                // The click belongs to the palette
+            } else if is_down && !was_down && tool == Tool::Fill {
+                world.flood_fill(cursor.0 as usize, cursor.1 as usize, element);
+            } else if is_down && !was_down && tool.is_shape() {
+                drag_start = Some(cursor);
+            } else if is_down && tool == Tool::Brush && brush_mode != BrushMode::Paint && !erasing {
+                flow_cells = tool.cells(cursor, cursor, radius);
+            } else if is_down && tool.is_continuous() {
+                // Paint along the way from the previous frame's cursor
+                let from = if was_down { last_cursor } else { cursor };
+                world.paint(&tool.cells(from, cursor, radius), element);
+            } else if !is_down {
+                if let Some(start) = drag_start.take() {
+                    world.paint(&tool.cells(start, cursor, radius), element);
                 }
             }
-        }
 
-        // remove any water that has left the map
-        for x in 0..WIDTH {
-            new_mass[x][0] = 0.0;
-            new_mass[x][HEIGHT - 1] = 0.0;
+            was_down = is_down;
+            last_cursor = cursor;
         }
 
-        for y in 0..HEIGHT {
-            new_mass[0][y] = 0.0;
-            new_mass[WIDTH - 1][y] = 0.0;
+        if tick_limit.is_some_and(|limit| tick_count >= limit) {
+            stepper.pause();
         }
 
-        self.mass = new_mass.clone();
-        self.new_mass = new_mass.clone();
-        self.blocks = blocks;
-    }
-
-    fn draw_element(&mut self, x: usize, y: usize) {
-        match self.selected_element {
-            Cell::Water => {
-                self.blocks[x][y] = Cell::Water;
-                // self.mass[x][y] = MAX_MASS;
-                self.mass[x][y] = MAX_MASS * 10.0;
-                self.mass[x + 1][y] = MAX_MASS * 10.0;
-                self.mass[x + 2][y] = MAX_MASS * 10.0;
-                self.mass[x - 1][y] = MAX_MASS * 10.0;
-                self.mass[x - 2][y] = MAX_MASS * 10.0;
-            }
-            Cell::Ground => {
-                self.blocks[x][y] = Cell::Ground;
-                self.blocks[x + 1][y] = Cell::Ground;
-                self.blocks[x - 1][y] = Cell::Ground;
-                self.blocks[x][y + 1] = Cell::Ground;
-                self.blocks[x][y - 1] = Cell::Ground;
+        // Fast-forward keeps ticking until it is time to show a frame
+        let frame_start = Instant::now();
+        let ticks = stepper.ticks_for_frame();
+        let mut frame_ticks = 0;
+        while frame_ticks < ticks || (stepper.fast_forward() && frame_start.elapsed() < stepping::FAST_FORWARD_FRAME) {
+            if tick_limit.is_some_and(|limit| tick_count >= limit) {
+                break;
             }
-            Cell::Air => {
-                self.mass[x][y] = 0.0;
-                self.mass[x + 1][y] = 0.0;
-                self.mass[x - 1][y] = 0.0;
-                self.mass[x][y + 1] = 0.0;
-                self.mass[x][y - 1] = 0.0;
+
+            let tick_start = Instant::now();
+            world.pour(&flow_cells, brush_mode.flow(flow_rate, world.params().max_mass));
+            world.tick();
+            tick_count += 1;
+            frame_ticks += 1;
+            tick_time = tick_start.elapsed();
+
+            if let Some(stats) = stats.as_mut() {
+                stats.write(&Stats {
+                    tick: tick_count,
+                    tick_time,
+                    total_mass: world.total_mass(),
+                })?;
             }
-            _ => (),
         }
-    }
 
-    fn get_water_color(&self, mut mass: f32) -> u32 {
-        mass = clamp(mass, MIN_MASS, MAX_MASS);
-        let mut g = 50.0;
-        let mut r = 50.0;
-        let mut b;
-
-        if (mass < 1.0) {
-            b = lerp_range(mass, 0.01, 1.0, 255.0, 200.0);
-            r = lerp_range(mass, 0.01, 1.0, 240.0, 50.0);
-            r = clamp(r, 50.0, 240.0);
-            g = r;
+        tps.count(frame_ticks);
+
+        world.render_view(&mut frame, view);
+        if smooth_surface && view == View::Normal {
+            camera.render(&mut buff, view_width, |position, size| world.smooth_pixel(&frame, position, size));
         } else {
-            b = lerp_range(mass, 1.0, 1.1, 90.0, 140.0);
+            camera.sample(&frame, &mut buff, view_width);
         }
-
-        (1 << 24) + ((r as u32) << 16) + ((g as u32) << 8) + b as u32
-    }
-
-    fn render(&self, buff: &mut [u32]) {
-        self.render_simulation(buff);
-        self.render_widgets(buff);
-    }
-
-    fn render_simulation(&self, buff: &mut [u32]) {
-        let blocks = self.blocks.clone();
-        let mass = self.mass.clone();
-
-        for y in 0..HEIGHT {
-            for x in 0..WIDTH {
-                let current_cell = self.blocks[x][y];
-
-                buff[y * WIDTH + x] = match current_cell {
-                    // Cell::Water => Color::Blue.get_hex(),
-                    Cell::Water => self.get_water_color(mass[x][y]),
-                    // Cell::Water => Color::Red.get_hex(),
-                    Cell::Air => Color::Black.get_hex(),
-                    Cell::Ground => Color::Desert.get_hex(),
-                    _ => 0,
-                }
+        fps.frame();
+
+        if show_hud {
+            palette::render(&world, &mut buff, view_width);
+
+            let hud = Hud {
+                fps: fps.fps(),
+                tps: tps.fps(),
+                tick_time,
+                total_mass: world.total_mass(),
+                tick: tick_count,
+                brush_size: radius,
+                element: world.selected_element().name(),
+                view: view.name(),
+                paused: stepper.is_paused(),
+                recording: recorder.is_some(),
+                stepping: stepper.status(),
+            };
+
+            // Top right, the palette is in the top left corner
+            hud::blit(&mut buff, view_width, &hud.render(), hud::WIDTH, (view_width.saturating_sub(hud::WIDTH), 0));
+
+            // Bottom left, under the palette
+            if let Some(range) = view.range(world.params()) {
+                let top = view_height.saturating_sub(views::LEGEND_HEIGHT);
+                hud::blit(&mut buff, view_width, &views::legend(view, range), views::LEGEND_WIDTH, (0, top));
             }
         }
-    }
 
-    fn render_widgets(&self, buff: &mut [u32]) {
-        let widgets = self.widgets.clone();
-        let mut x: usize = 5;
-        let y: usize = 5;
-        let square_length: usize = 5;
-        let padding: usize = 5;
-
-        for w in widgets {
-            self.render_rectangle(buff, Vec2::new(x, y), square_length, w.get_color());
-            x += square_length + padding;
+        if take_screenshot {
+            let path = image::timestamped_path(&options.screenshot_dir, "screenshot", "png", SystemTime::now());
+            image::save_png(&path, view_width, view_height, &buff)?;
+            println!("Saved {}", path.display());
+            take_screenshot = false;
         }
-    }
 
-    fn render_rectangle(&self, buff: &mut [u32], point: Vec2<usize>, length: usize, color: Color) {
-        for y in point.y..point.y + length {
-            for x in point.x..point.x + length {
-                buff[y * WIDTH + x] = color.get_hex();
+        if toggle_recording {
+            match recorder.take() {
+                Some(recording) => finish_recording(recording)?,
+                None => {
+                    let extension = options.record_format.extension();
+                    let path = image::timestamped_path(&options.screenshot_dir, "recording", extension, SystemTime::now());
+                    recorder = Some(Recorder::create(&path, options.record_format, (view_width, view_height), options.record)?);
+                    println!("Recording to {}", path.display());
+                }
             }
+            toggle_recording = false;
         }
-    }
-
-    fn select_element(&mut self, cell_element: Cell) {
-        self.selected_element = cell_element;
 
-        for mut w in self.widgets.iter_mut() {
-            if w.element == cell_element {
-                w.active = true;
-            } else {
-                w.active = false;
-            }
+        if let Some(recording) = recorder.as_mut() {
+            recording.frame(tick_count, &buff)?;
         }
-    }
-
-    fn rotate_canvas_anticlockwise(&mut self) {
-        let mut blocks = self.blocks.clone();
-
-        // Processing each block one by one
-        for i in 0..WIDTH / 2 {
-
-            // Processing elements in group of 4 in the current square
-            for j in i..HEIGHT - i - 1 {
-                // Storing current cell in a temporal variable
-                let tmp_block = blocks[i][j];
-
-                // Move values from right to top
-                blocks[i][j] = blocks[j][HEIGHT - 1 - i];
-
-                // Move values from bottom to right
-                blocks[j][HEIGHT - 1 - i] = blocks[HEIGHT - 1 - i][HEIGHT - 1 - j];
-
-                // Move values from left to bottom
-                blocks[HEIGHT - 1 - i][HEIGHT - 1 - j] = blocks[HEIGHT - 1 - j][i];
-
-                // Assign temporal to left
-                blocks[HEIGHT - 1 - j][i] = tmp_block;
-            }
+        if let Some(recording) = recorder.take_if(|recording| recording.is_full()) {
+            finish_recording(recording)?;
         }
 
-        self.blocks = blocks;
+        window.update_with_buffer(&buff, view_width, view_height)?;
     }
 
-    fn rotate_canvas_clockwise(&mut self) {
-        let mut blocks = self.blocks.clone();
-
-        // Traverse each cycle
-        for i in 0..WIDTH / 2 {
-            for j in i..HEIGHT - i - 1 {
-                // Swap elements of each cycle in clockwise direction
-                let tmp_block = blocks[i][j];
-                blocks[i][j] = blocks[HEIGHT - 1 - j][i];
-                blocks[HEIGHT - 1 - j][i] = blocks[HEIGHT - 1 - i][HEIGHT - 1 - j];
-                blocks[HEIGHT - 1 - i][HEIGHT - 1 - j] = blocks[j][HEIGHT - 1 - i];
-                blocks[j][HEIGHT - 1 - i] = tmp_block;
-            }
-        }
-
-        self.blocks = blocks;
+    if let Some(recording) = recorder {
+        finish_recording(recording)?;
     }
 
-    fn count_neighbours(&self, map: [[bool; WIDTH]; HEIGHT], x: usize, y: usize) -> i32 {
-        let mut count_n = 0;
-
-        for ii in 0..3 {
-            for jj in 0..3 {
-                let i = ii as i32 - 1;
-                let j = jj as i32 - 1;
+    Ok(())
+}
 
-                if i == 0 && j == 0 {
-                    continue;
-                }
+fn finish_recording(recorder: Recorder) -> Result<(), Box<dyn Error>> {
+    let (frames, path) = (recorder.frames(), recorder.path().to_path_buf());
+    recorder.finish()?;
+    println!("Saved {} frames to {}", frames, path.display());
 
-                let n_x = x as i32 + i;
-                let n_y = y as i32 + j;
+    Ok(())
+}
 
-                if n_x < 0 || n_y < 0 || n_x >= WIDTH as i32 || n_y >= HEIGHT as i32 {
-                    count_n += 1;
-                } else if map[n_x as usize][n_y as usize] {
-                    count_n += 1;
-                }
-            }
-        }
+fn gpu_rendering(options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut config = Config::default();
 
-        count_n
+    if let Some((width, height)) = options.size {
+        config.field_width = width;
+        config.field_height = height;
     }
-
-    fn do_cave_generation_step(&self, old_map: [[bool; WIDTH]; HEIGHT]) -> [[bool; WIDTH]; HEIGHT] {
-        let mut new_map = [[false; WIDTH]; HEIGHT];
-        let death_limit = 3;
-        let birth_limit = 4;
-        // let birth_limit = 3;
-
-        for i in 0..WIDTH {
-            for j in 0..HEIGHT {
-                let nbs = self.count_neighbours(old_map, i, j);
-                if old_map[i][j] {
-                    if nbs < death_limit {
-                        new_map[i][j] = false;
-                    } else {
-                        new_map[i][j] = true;
-                    }
-                } else {
-                    if nbs > birth_limit {
-                        new_map[i][j] = true;
-                    } else {
-                        new_map[i][j] = false;
-                    }
-                }
-            }
-        }
-
-        new_map
+    if let Some(rate) = options.update_rate {
+        config.update_rate = rate;
     }
-
-    fn initialize_cave(&self) -> [[bool; WIDTH]; HEIGHT] {
-        let mut cave_map = [[false; WIDTH]; HEIGHT];
-        let chance_to_start_alive = 0.35;
-
-        for i in 0..WIDTH {
-            for j in 0..HEIGHT {
-                let chance: f64 = rand::thread_rng().gen();
-                if (chance < chance_to_start_alive) {
-                    cave_map[i][j] = true;
-                    // blocks[i][j] = Cell::Ground;
-                }
-            }
-        }
-
-        cave_map
+    if let Some(max_lag) = options.max_lag {
+        config.max_lag = max_lag;
+    }
+    if let Some(path) = &options.scenario {
+        config.scenario = Some(Scenario::load(path)?);
+    }
+    if let Some(path) = &options.load {
+        config.grid = Some(std::fs::read_to_string(path)?.parse()?);
     }
 
-    fn generate_map(&mut self) {
-        self.clear_map();
-        let mut blocks = self.blocks.clone();
-        let mut cave_map = self.initialize_cave();
-
-        for _ in 0..3 {
-            cave_map = self.do_cave_generation_step(cave_map);
-        }
+    config.window_scale = options.scale;
+    config.ticks_per_frame = options.ticks_per_frame;
+    config.vsync = options.vsync;
+    config.flow_rate = options.flow_rate;
+    config.seed = options.seed;
+    config.tick_limit = options.ticks;
+    config.stats_path = options.stats.clone();
+    config.screenshot_dir = options.screenshot_dir.clone();
+    config.record_format = options.record_format;
+    config.record = options.record;
+
+    automata_sandbox::run_simulation(config)
+}
 
-        for i in 0..WIDTH {
-            for j in 0..HEIGHT {
-                if cave_map[i][j] {
-                    blocks[i][j] = Cell::Ground;
-                } else {
-                    blocks[i][j] = Cell::Air;
-                }
-            }
+fn main() {
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, cli::USAGE);
+            process::exit(2);
         }
+    };
 
-        self.blocks = blocks;
+    if options.help {
+        print!("{}", cli::USAGE);
+        return;
     }
-}
-
-fn cpu_rendering() {
-    let mut world = World::new();
-
-    let mut buff = vec![0; WIDTH * HEIGHT];
-    let mut window = Window::new(
-        "CA Water Simulation",
-        WIDTH,
-        HEIGHT,
-        WindowOptions {
-            scale: minifb::Scale::X2,
-            ..WindowOptions::default()
-        },
-    ).unwrap_or_else(|e| {
-        panic!("{}", e);
-    });
 
-    window.limit_update_rate(Some(std::time::Duration::from_micros(FRAME_DELAY)));
-    window.set_cursor_style(CursorStyle::Crosshair);
-
-    // let mut fps_now = Instant::now();
-    // let mut fps_time_diff;
-    // let mut fps_counter = 0;
-    // let mut tick_time;
-
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        window.get_keys_pressed(KeyRepeat::No).map(|keys| {
-            for t in keys {
-                match t {
-                    Key::Key1 => world.select_element(Cell::Ground),
-                    Key::Key2 => world.select_element(Cell::Water),
-                    Key::E => world.rotate_canvas_anticlockwise(),
-                    Key::R => world.rotate_canvas_clockwise(),
-                    Key::N => world.generate_map(),
-                    Key::C => world.clear_map(),
-                    _ => (),
-                }
-            }
-        }).unwrap();
-
-        if window.get_mouse_down(MouseButton::Left) {
-            window.get_mouse_pos(MouseMode::Discard).map(|(x, y)| {
-                world.draw_element(x as usize, y as usize);
-            });
+    let result = if options.validate {
+        validate()
+    } else if let Some(sizes) = &options.compare {
+        compare(&options, sizes)
+    } else if options.headless {
+        headless(&options)
+    } else {
+        match options.backend {
+            Backend::Cpu => cpu_rendering(&options),
+            Backend::Gpu => gpu_rendering(&options),
         }
+    };
 
-        // window.get_scroll_wheel().map(|scroll| {
-        //     println!("-> {:?}", scroll);
-        // });
-        // tick_time = Instant::now();
-
-        world.tick();
-        world.render(&mut buff);
-
-        window
-            .update_with_buffer(&buff, WIDTH, HEIGHT)
-            .unwrap();
-
-        // println!("Tick Time: {}", tick_time.elapsed().as_micros());
-
-        // FPS Calculation
-        // fps_counter += 1;
-        // fps_time_diff = fps_now.elapsed().as_secs();
-
-        // if fps_time_diff > 1 {
-            // println!("FPS: {}", fps_counter as f64 / fps_time_diff as f64);
-            // fps_counter = 0;
-            // fps_now = Instant::now();
-        // }
-    }
-}
-
-fn gpu_rendering() {
-    if let Err(failure) = automata_sandbox::run_simulation() {
+    if let Err(failure) = result {
         eprintln!("Application failed: {}", failure);
+        process::exit(1);
     }
 }
-
-fn main() {
-    // cpu_rendering();
-    gpu_rendering();
-}
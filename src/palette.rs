@@ -0,0 +1,136 @@
+//! Element palette of the CPU frontend.
+//!
+//! A strip in the top left corner of the window lists every element with its
+//! colour and name, followed by buttons that clear, generate and rotate the
+//! map. The frontend asks the palette about a click before the painting tools
+//! see it, so clicking on the strip never paints the cells under it.
+
+use crate::hud::{self, BACKGROUND, FOREGROUND, GLYPH_HEIGHT};
+use crate::world::{Cell, World};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Action {
+    Select(Cell),
+    Clear,
+    Generate,
+    Rotate,
+}
+
+impl Action {
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Select(element) => element.name(),
+            Action::Clear => "clear",
+            Action::Generate => "generate",
+            Action::Rotate => "rotate",
+        }
+    }
+
+    pub fn apply(self, world: &mut World) {
+        match self {
+            Action::Select(element) => world.select_element(element),
+            Action::Clear => world.clear_map(),
+            Action::Generate => world.generate_map(),
+            Action::Rotate => world.rotate_canvas_clockwise(),
+        }
+    }
+}
+
+/// Every entry of the strip, top to bottom
+pub const ACTIONS: &[Action] = &[
+    Action::Select(Cell::Ground),
+    Action::Select(Cell::Water),
+    Action::Select(Cell::Air),
+    Action::Clear,
+    Action::Generate,
+    Action::Rotate,
+];
+
+const PADDING: usize = 2;
+const ROW_HEIGHT: usize = GLYPH_HEIGHT + 2;
+/// Side of the colour swatch in front of an element's name
+const SWATCH: usize = GLYPH_HEIGHT;
+
+const SELECTED: u32 = 0xffff00;
+const BUTTON: u32 = 0x404040;
+
+fn label_x() -> usize {
+    PADDING + SWATCH + 2
+}
+
+/// Size of the strip in pixels
+pub fn size() -> (usize, usize) {
+    let longest = ACTIONS.iter().map(|action| hud::text_width(action.label())).max().unwrap_or(0);
+
+    (label_x() + longest + PADDING, 2 * PADDING + ACTIONS.len() * ROW_HEIGHT)
+}
+
+/// The entry under `(x, y)`, if the strip covers that pixel
+pub fn hit(x: usize, y: usize) -> Option<Action> {
+    let (width, height) = size();
+
+    if x >= width || y < PADDING || y >= height - PADDING {
+        return None;
+    }
+
+    ACTIONS.get((y - PADDING) / ROW_HEIGHT).copied()
+}
+
+/// Whether the strip covers `(x, y)`, padding included
+pub fn contains(x: usize, y: usize) -> bool {
+    let (width, height) = size();
+
+    x < width && y < height
+}
+
+/// Draws the strip onto a frame buffer that is `width` pixels wide
+pub fn render(world: &World, buff: &mut [u32], width: usize) {
+    let (strip_width, strip_height) = size();
+    let mut strip = vec![BACKGROUND; strip_width * strip_height];
+
+    for (row, &action) in ACTIONS.iter().enumerate() {
+        let top = PADDING + row * ROW_HEIGHT;
+        let mut color = FOREGROUND;
+
+        match action {
+            Action::Select(element) => {
+                let swatch = world.element_color(element);
+                for y in top + 1..top + 1 + SWATCH {
+                    for x in PADDING..PADDING + SWATCH {
+                        strip[x + y * strip_width] = swatch;
+                    }
+                }
+
+                if element == world.selected_element() {
+                    color = SELECTED;
+                }
+            }
+            _ => {
+                for y in top..top + ROW_HEIGHT - 1 {
+                    for x in PADDING..strip_width - PADDING {
+                        strip[x + y * strip_width] = BUTTON;
+                    }
+                }
+            }
+        }
+
+        hud::draw_text(&mut strip, strip_width, (label_x(), top + 1), action.label(), color);
+    }
+
+    hud::blit(buff, width, &strip, strip_width, (0, 0));
+}
+
+#[test]
+fn test_hit_finds_every_entry() {
+    for (row, &action) in ACTIONS.iter().enumerate() {
+        let y = PADDING + row * ROW_HEIGHT + ROW_HEIGHT / 2;
+
+        assert_eq!(hit(PADDING, y), Some(action));
+        assert!(contains(PADDING, y));
+    }
+
+    let (width, height) = size();
+    assert_eq!(hit(width, PADDING), None);
+    assert_eq!(hit(0, height), None);
+    assert!(!contains(width, 0));
+}
@@ -0,0 +1,90 @@
+use crate::world::{MAX_COMPRESS, MAX_MASS, MAX_SPEED, MIN_FLOW, MIN_MASS};
+
+/// Tunable constants of the flow rules. The CPU world and the compute shader
+/// share the same knobs, but start from different defaults.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Params {
+    /// Scale of the compression curve used to split water between two vertically adjacent cells
+    pub max_mass: f32,
+    /// Extra mass a cell may hold for every cell of water stacked above it
+    pub max_compress: f32,
+    /// Cells holding less than this are considered empty
+    pub min_mass: f32,
+    /// Flows above this are damped by `flow_speed`
+    pub min_flow: f32,
+    /// Upper bound for the mass moved vertically in a single tick
+    pub max_speed: f32,
+    /// Multiplier applied to flows larger than `min_flow`
+    pub flow_speed: f32,
+}
+
+impl Params {
+    /// Constants used by `World::tick`
+    pub fn cpu() -> Self {
+        Params {
+            max_mass: MAX_MASS,
+            max_compress: MAX_COMPRESS,
+            min_mass: MIN_MASS,
+            min_flow: MIN_FLOW,
+            max_speed: MAX_SPEED,
+            flow_speed: 0.8,
+        }
+    }
+
+    /// Constants used by the compute shader
+    pub fn gpu() -> Self {
+        Params {
+            max_mass: 1.0,
+            max_compress: 0.625,
+            min_mass: 0.005,
+            min_flow: 0.01,
+            max_speed: 4.0,
+            flow_speed: 1.0,
+        }
+    }
+
+    /// The constants of the tutorial this simulation is based on, see the README
+    pub fn tutorial() -> Self {
+        Params {
+            max_mass: 1.0,
+            max_compress: 0.02,
+            min_mass: 0.0001,
+            min_flow: 0.01,
+            max_speed: 1.0,
+            flow_speed: 0.5,
+        }
+    }
+
+    /// Names accepted by `Params::preset`
+    pub const PRESETS: &'static [&'static str] = &["cpu", "gpu", "tutorial"];
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "cpu" => Some(Params::cpu()),
+            "gpu" => Some(Params::gpu()),
+            "tutorial" => Some(Params::tutorial()),
+            _ => None,
+        }
+    }
+
+    /// Overrides a single constant by its name, as written in scenario files
+    pub fn set(&mut self, name: &str, value: f32) -> Result<(), String> {
+        match name {
+            "max_mass" => self.max_mass = value,
+            "max_compress" => self.max_compress = value,
+            "min_mass" => self.min_mass = value,
+            "min_flow" => self.min_flow = value,
+            "max_speed" => self.max_speed = value,
+            "flow_speed" => self.flow_speed = value,
+            _ => return Err(format!("unknown parameter '{}'", name)),
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params::cpu()
+    }
+}
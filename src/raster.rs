@@ -0,0 +1,103 @@
+//! A CPU port of `shaders/composition.frag`.
+//!
+//! It colours a GPU cell buffer the way the fragment shader does, with the same
+//! HSV mapping and the same `rand(xy)` noise, so images of GPU states can be
+//! made without an OpenGL context. Only the normal view is ported, without the
+//! brush ring. The shader's float maths is followed step by step in `f32`, but
+//! `sin` differs between drivers, so the noise may be off by a shade.
+
+use crate::{Cell, CellType};
+
+const CELL_BLOCK: i32 = CellType::Block as i32;
+const CELL_WATER: i32 = CellType::Water as i32;
+const CELL_ACID: i32 = CellType::Acid as i32;
+/// Only known to the shaders
+const CELL_SAND: i32 = 4;
+/// Marks cells the compute shader flagged, drawn in plain red
+const CELL_DEBUG: i32 = 99;
+
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+fn hsv2rgb([h, s, v]: [f32; 3]) -> [f32; 3] {
+    let k = [1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0];
+    let channel = |offset: f32| {
+        let p = (fract(h + offset) * 6.0 - k[3]).abs();
+        v * mix(k[0], (p - k[0]).clamp(0.0, 1.0), s)
+    };
+
+    [channel(k[0]), channel(k[1]), channel(k[2])]
+}
+
+/// The shader's noise, from 0 to 1 and the same for every frame
+pub fn rand((x, y): (f32, f32)) -> f32 {
+    // 43758.5453 in the shader, the closest f32 is written out
+    fract((x * 12.9898 + y * 78.233).sin() * 43_758.547)
+}
+
+/// Quantised like a write to the default framebuffer
+fn to_pixel(rgb: [f32; 3]) -> u32 {
+    rgb.iter().fold(0, |pixel, channel| (pixel << 8) | (channel.clamp(0.0, 1.0) * 255.0).round() as u32)
+}
+
+/// Colour of `cell` at `(x, y)` in field coordinates, as `0xRRGGBB`
+pub fn cell_color(cell: Cell, (x, y): (usize, usize)) -> u32 {
+    let noise = rand((x as f32, y as f32));
+
+    to_pixel(match cell.element_type {
+        CELL_DEBUG => [1.0, 0.0, 0.0],
+        CELL_ACID => [0.0, noise, 0.0],
+        CELL_SAND => [1.0, 1.0, 0.0],
+        CELL_WATER => hsv2rgb([0.61, 1.0, mix(0.7, 1.0, cell.mass)]),
+        CELL_BLOCK => hsv2rgb([0.075, 0.6, mix(noise, 0.46, 0.77)]),
+        _ => hsv2rgb([0.0, 0.0, noise.clamp(0.0, 0.15)]),
+    })
+}
+
+/// Renders a GPU cell buffer, whose first row is the bottom of the field, into
+/// an image of `width` by `height` pixels with the top row first
+pub fn render_cells(width: usize, height: usize, cells: &[Cell]) -> Vec<u32> {
+    let mut image = vec![0; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            image[x + (height - 1 - y) * width] = cell_color(cells[x + y * width], (x, y));
+        }
+    }
+
+    image
+}
+
+#[test]
+fn test_colors_follow_the_shader() {
+    let water = Cell { element_type: CELL_WATER, mass: 1.0 };
+    // hsv(0.61, 1, 1) is (0, 0.34, 1)
+    assert_eq!(cell_color(water, (0, 0)), 0x0057ff);
+
+    // Overfull water saturates like the framebuffer does
+    let compressed = Cell { element_type: CELL_WATER, mass: 3.0 };
+    assert_eq!(cell_color(compressed, (0, 0)) & 0xff, 0xff);
+
+    // Empty cells are dark grey noise, the same on every call
+    let empty = Cell::default();
+    let color = cell_color(empty, (7, 3));
+    assert_eq!(color, cell_color(empty, (7, 3)));
+    assert_eq!(color >> 16, color & 0xff);
+    assert!(color & 0xff <= 38);
+}
+
+#[test]
+fn test_render_cells_puts_the_bottom_row_last() {
+    let block = Cell { element_type: CELL_BLOCK, mass: 0.0 };
+    let water = Cell { element_type: CELL_WATER, mass: 1.0 };
+    let image = render_cells(2, 2, &[block, block, water, water]);
+
+    assert_eq!(image[0], cell_color(water, (0, 1)));
+    assert_eq!(image[2], cell_color(block, (0, 0)));
+    assert_eq!(image[3], cell_color(block, (1, 0)));
+}
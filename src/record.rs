@@ -0,0 +1,490 @@
+//! Recording rendered frames as animated GIFs or Y4M videos.
+//!
+//! Frames are `0xRRGGBB` buffers with the top row first, like the images of
+//! `image`. GIF frames get a palette of their own 256 most common colours,
+//! exact when a frame has no more than that, and are LZW compressed. Y4M is an
+//! uncompressed 4:4:4 stream that video tools such as ffmpeg read directly.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::image;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Format {
+    Gif,
+    Y4m,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Gif => "gif",
+            Format::Y4m => "y4m",
+        }
+    }
+
+    /// The format matching the extension of `path`
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .parse()
+            .map_err(|_| format!("cannot record to '{}', expected a .gif or .y4m file", path.display()))
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gif" => Ok(Format::Gif),
+            "y4m" => Ok(Format::Y4m),
+            _ => Err(format!("unknown recording format '{}', expected gif or y4m", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RecordOptions {
+    /// Record a frame every this many ticks
+    pub stride: u64,
+    /// Pixels of the recording per pixel of the frames
+    pub scale: usize,
+    /// Stop after this many frames
+    pub max_frames: Option<usize>,
+    /// Playback rate of the recording
+    pub fps: u32,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        RecordOptions {
+            stride: 1,
+            scale: 1,
+            max_frames: None,
+            fps: 30,
+        }
+    }
+}
+
+/// Writes the frames of one recording as they come
+pub struct Recorder {
+    out: BufWriter<File>,
+    path: PathBuf,
+    format: Format,
+    /// Size of the frames passed in, before scaling
+    frame_size: (usize, usize),
+    options: RecordOptions,
+    frames: usize,
+    /// The first tick due for a frame
+    next_tick: u64,
+}
+
+impl Recorder {
+    /// Starts a recording of frames `frame_size` large at `path`
+    pub fn create(path: &Path, format: Format, frame_size: (usize, usize), options: RecordOptions) -> Result<Self, Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut recorder = Recorder {
+            out: BufWriter::new(File::create(path)?),
+            path: path.to_path_buf(),
+            format,
+            frame_size,
+            options,
+            frames: 0,
+            next_tick: 0,
+        };
+
+        let (width, height) = recorder.size();
+        match format {
+            Format::Gif => write_gif_header(&mut recorder.out, width, height)?,
+            Format::Y4m => writeln!(recorder.out, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", width, height, options.fps)?,
+        }
+
+        Ok(recorder)
+    }
+
+    /// Size of the recording in pixels
+    pub fn size(&self) -> (usize, usize) {
+        (self.frame_size.0 * self.options.scale, self.frame_size.1 * self.options.scale)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn frame_size(&self) -> (usize, usize) {
+        self.frame_size
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Whether the recording holds `max_frames` frames
+    pub fn is_full(&self) -> bool {
+        self.options.max_frames.is_some_and(|max| self.frames >= max)
+    }
+
+    /// Whether the frame of `tick` would be recorded: `stride` ticks have
+    /// passed since the last recorded one and the recording is not full
+    pub fn wants(&self, tick: u64) -> bool {
+        tick >= self.next_tick && !self.is_full()
+    }
+
+    /// Offers the frame rendered at `tick`, it is only recorded if `wants(tick)`
+    pub fn frame(&mut self, tick: u64, pixels: &[u32]) -> Result<(), Box<dyn Error>> {
+        if !self.wants(tick) {
+            return Ok(());
+        }
+
+        let (width, height) = self.size();
+        let pixels = image::scale(pixels, self.frame_size.0, self.options.scale);
+        match self.format {
+            Format::Gif => write_gif_frame(&mut self.out, width, height, &pixels, self.options.fps)?,
+            Format::Y4m => write_y4m_frame(&mut self.out, &pixels)?,
+        }
+
+        self.frames += 1;
+        self.next_tick = tick + self.options.stride;
+
+        Ok(())
+    }
+
+    /// Completes the file, a recording that is dropped instead may be cut off
+    pub fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        if self.format == Format::Gif {
+            // Trailer
+            self.out.write_all(&[0x3b])?;
+        }
+
+        self.out.flush()?;
+
+        Ok(())
+    }
+}
+
+fn write_gif_header(out: &mut impl Write, width: usize, height: usize) -> Result<(), Box<dyn Error>> {
+    out.write_all(b"GIF89a")?;
+    // Logical screen without a global colour table
+    out.write_all(&(width as u16).to_le_bytes())?;
+    out.write_all(&(height as u16).to_le_bytes())?;
+    out.write_all(&[0, 0, 0])?;
+    // Loop forever
+    out.write_all(&[0x21, 0xff, 11])?;
+    out.write_all(b"NETSCAPE2.0")?;
+    out.write_all(&[3, 1, 0, 0, 0])?;
+
+    Ok(())
+}
+
+fn write_gif_frame(out: &mut impl Write, width: usize, height: usize, pixels: &[u32], fps: u32) -> Result<(), Box<dyn Error>> {
+    let (palette, indices) = quantize(pixels);
+    // In hundredths of a second
+    let delay = (100.0 / fps.max(1) as f32).round().max(1.0) as u16;
+
+    // Graphic control extension
+    out.write_all(&[0x21, 0xf9, 4, 0])?;
+    out.write_all(&delay.to_le_bytes())?;
+    out.write_all(&[0, 0])?;
+
+    // Image descriptor with a local colour table of 256 entries
+    out.write_all(&[0x2c, 0, 0, 0, 0])?;
+    out.write_all(&(width as u16).to_le_bytes())?;
+    out.write_all(&(height as u16).to_le_bytes())?;
+    out.write_all(&[0x80 | 7])?;
+
+    for i in 0..256 {
+        let color = palette.get(i).copied().unwrap_or(0);
+        out.write_all(&[(color >> 16) as u8, (color >> 8) as u8, color as u8])?;
+    }
+
+    out.write_all(&[8])?;
+    for block in lzw(&indices).chunks(255) {
+        out.write_all(&[block.len() as u8])?;
+        out.write_all(block)?;
+    }
+    out.write_all(&[0])?;
+
+    Ok(())
+}
+
+/// Colour of a bin of 5 bits per channel
+fn bin(color: u32) -> usize {
+    (((color >> 19) & 0x1f) << 10 | ((color >> 11) & 0x1f) << 5 | ((color >> 3) & 0x1f)) as usize
+}
+
+fn distance(a: u32, b: u32) -> i32 {
+    [16, 8, 0].iter().map(|shift| {
+        let d = ((a >> shift) & 0xff) as i32 - ((b >> shift) & 0xff) as i32;
+        d * d
+    }).sum()
+}
+
+/// Up to 256 colours for a frame and the index of every pixel in them
+fn quantize(pixels: &[u32]) -> (Vec<u32>, Vec<u8>) {
+    let mut exact: HashMap<u32, u8> = HashMap::new();
+    for &pixel in pixels {
+        let next = exact.len();
+        if next > 256 {
+            break;
+        }
+        exact.entry(pixel & 0xffffff).or_insert(next as u8);
+    }
+
+    if exact.len() <= 256 {
+        let mut palette = vec![0; exact.len()];
+        for (&color, &index) in &exact {
+            palette[index as usize] = color;
+        }
+
+        return (palette, pixels.iter().map(|pixel| exact[&(pixel & 0xffffff)]).collect());
+    }
+
+    // The most common bins, each shown as the mean of its pixels
+    let mut bins = vec![(0u32, [0u64; 3]); 1 << 15];
+    for &pixel in pixels {
+        let (count, sum) = &mut bins[bin(pixel)];
+        *count += 1;
+        for (channel, shift) in sum.iter_mut().zip([16, 8, 0]) {
+            *channel += ((pixel >> shift) & 0xff) as u64;
+        }
+    }
+
+    let mut popular: Vec<usize> = (0..bins.len()).filter(|&i| bins[i].0 > 0).collect();
+    popular.sort_by_key(|&i| std::cmp::Reverse(bins[i].0));
+    popular.truncate(256);
+
+    let palette: Vec<u32> = popular.iter().map(|&i| {
+        let (count, sum) = bins[i];
+        sum.iter().fold(0, |color, &channel| (color << 8) | (channel / count as u64) as u32)
+    }).collect();
+
+    let mut nearest: HashMap<usize, u8> = HashMap::new();
+    let indices = pixels.iter().map(|&pixel| {
+        *nearest.entry(bin(pixel)).or_insert_with(|| {
+            (0..palette.len()).min_by_key(|&i| distance(pixel, palette[i])).unwrap() as u8
+        })
+    }).collect();
+
+    (palette, indices)
+}
+
+const CLEAR: u16 = 256;
+const END: u16 = 257;
+const MAX_CODES: u16 = 4096;
+
+/// Packs codes of growing width, least significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u32) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += width;
+
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+
+        self.bytes
+    }
+}
+
+/// GIF flavoured LZW of 8 bit indices
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { bytes: Vec::new(), buffer: 0, bits: 0 };
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut width = 9;
+    let mut next = END + 1;
+
+    writer.write(CLEAR, width);
+
+    let Some((&first, rest)) = indices.split_first() else {
+        writer.write(END, width);
+        return writer.finish();
+    };
+
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = dictionary.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+
+        writer.write(prefix, width);
+        // The decoder widens its codes one entry later than it could
+        if next >= 1 << width && width < 12 {
+            width += 1;
+        }
+
+        if next < MAX_CODES {
+            dictionary.insert((prefix, index), next);
+            next += 1;
+        } else {
+            writer.write(CLEAR, width);
+            dictionary.clear();
+            width = 9;
+            next = END + 1;
+        }
+
+        prefix = index as u16;
+    }
+
+    writer.write(prefix, width);
+    if next >= 1 << width && width < 12 {
+        width += 1;
+    }
+    writer.write(END, width);
+
+    writer.finish()
+}
+
+fn write_y4m_frame(out: &mut impl Write, pixels: &[u32]) -> Result<(), Box<dyn Error>> {
+    let rgb = |pixel: u32| (((pixel >> 16) & 0xff) as i32, ((pixel >> 8) & 0xff) as i32, (pixel & 0xff) as i32);
+
+    // BT.601 in the studio range
+    let y: Vec<u8> = pixels.iter().map(|&p| {
+        let (r, g, b) = rgb(p);
+        (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8
+    }).collect();
+    let u: Vec<u8> = pixels.iter().map(|&p| {
+        let (r, g, b) = rgb(p);
+        (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8
+    }).collect();
+    let v: Vec<u8> = pixels.iter().map(|&p| {
+        let (r, g, b) = rgb(p);
+        (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8
+    }).collect();
+
+    out.write_all(b"FRAME\n")?;
+    out.write_all(&y)?;
+    out.write_all(&u)?;
+    out.write_all(&v)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn unlzw(bytes: &[u8]) -> Vec<u8> {
+    let mut table: Vec<Vec<u8>> = Vec::new();
+    let mut width = 9;
+    let mut previous: Option<usize> = None;
+    let mut decoded = Vec::new();
+    let (mut buffer, mut bits, mut at) = (0u32, 0, 0);
+
+    loop {
+        while bits < width {
+            buffer |= (bytes[at] as u32) << bits;
+            at += 1;
+            bits += 8;
+        }
+        let code = (buffer & ((1 << width) - 1)) as usize;
+        buffer >>= width;
+        bits -= width;
+
+        if code == CLEAR as usize {
+            table = (0..=END).map(|i| vec![i as u8]).collect();
+            width = 9;
+            previous = None;
+            continue;
+        } else if code == END as usize {
+            return decoded;
+        }
+
+        let entry = match table.get(code) {
+            Some(entry) => entry.clone(),
+            None => {
+                let mut entry = table[previous.unwrap()].clone();
+                entry.push(entry[0]);
+                entry
+            }
+        };
+
+        if let Some(previous) = previous {
+            if table.len() < MAX_CODES as usize {
+                let mut added = table[previous].clone();
+                added.push(entry[0]);
+                table.push(added);
+            }
+        }
+
+        if table.len() == 1 << width && width < 12 {
+            width += 1;
+        }
+
+        decoded.extend_from_slice(&entry);
+        previous = Some(code);
+    }
+}
+
+#[test]
+fn test_lzw_round_trips() {
+    // Long enough to fill the dictionary and start over
+    let mut state = 7u32;
+    let indices: Vec<u8> = (0..20_000).map(|i| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        if i % 3 == 0 { (state >> 24) as u8 } else { (i / 50 % 7) as u8 }
+    }).collect();
+
+    assert_eq!(unlzw(&lzw(&indices)), indices);
+    assert_eq!(unlzw(&lzw(&[5, 5, 5, 5, 5, 5])), vec![5; 6]);
+    assert_eq!(unlzw(&lzw(&[])), Vec::<u8>::new());
+}
+
+#[test]
+fn test_quantize_keeps_few_colours_exact() {
+    let pixels = [0xff0000, 0x00ff00, 0xff0000, 0x0000ff];
+    let (palette, indices) = quantize(&pixels);
+
+    let restored: Vec<u32> = indices.iter().map(|&i| palette[i as usize]).collect();
+    assert_eq!(restored, pixels);
+
+    // A gradient of more colours than fit keeps every pixel close
+    let gradient: Vec<u32> = (0..1024).map(|i| (i as u32 * 7) << 8 | (i as u32 / 4)).collect();
+    let (palette, indices) = quantize(&gradient);
+    assert!(palette.len() <= 256);
+    assert!(gradient.iter().zip(&indices).all(|(&pixel, &i)| distance(pixel, palette[i as usize]) < 3 * 16 * 16));
+}
+
+#[test]
+fn test_recorder_follows_stride_and_length() {
+    let path = std::env::temp_dir().join(format!("automata-record-{}.y4m", std::process::id()));
+    let options = RecordOptions { stride: 3, scale: 2, max_frames: Some(2), fps: 25 };
+    let mut recorder = Recorder::create(&path, Format::Y4m, (2, 1), options).unwrap();
+
+    for tick in 0..10 {
+        recorder.frame(tick, &[0xffffff, 0x000000]).unwrap();
+    }
+    assert_eq!(recorder.frames(), 2);
+    assert!(recorder.is_full());
+    recorder.finish().unwrap();
+
+    let header = "YUV4MPEG2 W4 H2 F25:1 Ip A1:1 C444\n";
+    let data = fs::read(&path).unwrap();
+    assert_eq!(&data[..header.len()], header.as_bytes());
+    assert_eq!(data.len(), header.len() + 2 * ("FRAME\n".len() + 3 * 4 * 2));
+    // White in the studio range
+    assert_eq!(data[header.len() + "FRAME\n".len()], 235);
+
+    fs::remove_file(path).unwrap();
+}
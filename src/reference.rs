@@ -0,0 +1,246 @@
+//! A CPU port of `shaders/compute.shader`.
+//!
+//! It runs the same flow rules over the same cell layout as the GPU, one
+//! invocation after the other, so the GPU rules can be run headless and
+//! tested without an OpenGL context. Where the shader relies on unspecified
+//! behaviour the port picks a deterministic answer: invocations run in index
+//! order, and neighbours outside of the field behave like blocks.
+
+use crate::params::Params;
+use crate::scenario::Emitter;
+use crate::{Cell, CellType};
+
+const CELL_EMPTY: i32 = CellType::Empty as i32;
+const CELL_BLOCK: i32 = CellType::Block as i32;
+const CELL_WATER: i32 = CellType::Water as i32;
+const CELL_ACID: i32 = CellType::Acid as i32;
+
+pub struct Reference {
+    width: usize,
+    height: usize,
+    /// `curr_gen` in the shader, first row at the bottom of the field
+    cells: Vec<Cell>,
+    /// `mass_buffer` in the shader, it outlives every generation
+    mass: Vec<f32>,
+    params: Params,
+    /// Emitters in field coordinates, `y` growing upwards
+    emitters: Vec<Emitter>,
+}
+
+impl Reference {
+    pub fn new(width: usize, height: usize, cells: Vec<Cell>) -> Self {
+        assert_eq!(cells.len(), width * height);
+
+        // The GPU frontend seeds the mass buffer from the initial cells as well
+        let mass = cells.iter().map(|cell| cell.mass).collect();
+
+        Reference {
+            width,
+            height,
+            cells,
+            mass,
+            params: Params::gpu(),
+            emitters: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, params: Params) {
+        self.params = params;
+    }
+
+    pub fn set_emitters(&mut self, emitters: Vec<Emitter>) {
+        self.emitters = emitters;
+    }
+
+    pub fn total_mass(&self) -> f32 {
+        self.mass.iter().sum()
+    }
+
+    /// The mass buffer, which already holds the mass of the next generation
+    pub fn masses(&self) -> &[f32] {
+        &self.mass
+    }
+
+    fn neighbour(&self, x: usize, y: usize, dx: i32, dy: i32) -> Option<usize> {
+        let nx = x as i32 + dx;
+        let ny = y as i32 + dy;
+
+        if nx < 0 || ny < 0 || nx >= self.width as i32 || ny >= self.height as i32 {
+            None
+        } else {
+            Some(nx as usize + ny as usize * self.width)
+        }
+    }
+
+    fn get_vertical_flow_value(&self, total_mass: f32) -> f32 {
+        let max_mass = self.params.max_mass;
+        let max_compression = self.params.max_compress;
+
+        if total_mass <= max_mass {
+            max_mass
+        } else if total_mass < 2.0 * max_mass + max_compression {
+            (max_mass * max_mass + total_mass * max_compression) / (max_mass + max_compression)
+        } else {
+            (total_mass + max_compression) / 2.0
+        }
+    }
+
+    /// Moves `flow` from `xy` to the neighbour, if there is one. A neighbour outside of the
+    /// field never accepts any mass.
+    fn transfer(&mut self, xy: usize, to: Option<usize>, flow: f32) -> f32 {
+        match to {
+            Some(to) => {
+                self.mass[xy] -= flow;
+                self.mass[to] += flow;
+                flow
+            }
+            None => 0.0,
+        }
+    }
+
+    fn is_open(&self, idx: Option<usize>) -> bool {
+        idx.is_some_and(|idx| self.cells[idx].element_type != CELL_BLOCK)
+    }
+
+    fn cell_mass(&self, idx: Option<usize>) -> f32 {
+        idx.map_or(0.0, |idx| self.cells[idx].mass)
+    }
+
+    /// One invocation of the shader's `main`, up to the point where it reads back the mass buffer
+    fn flow(&mut self, x: usize, y: usize) {
+        let Params { min_flow, max_speed: max_flow, flow_speed, .. } = self.params;
+
+        let xy = x + y * self.width;
+        let xy_above = self.neighbour(x, y, 0, 1);
+        let xy_below = self.neighbour(x, y, 0, -1);
+        let xy_right = self.neighbour(x, y, 1, 0);
+        let xy_left = self.neighbour(x, y, -1, 0);
+
+        let curr = self.cells[xy];
+
+        if curr.element_type != CELL_BLOCK {
+            for i in 0..self.emitters.len() {
+                let e = self.emitters[i];
+                let (dx, dy) = (x as f32 - e.x, y as f32 - e.y);
+                if (dx * dx + dy * dy).sqrt() <= e.radius {
+                    self.mass[xy] = (self.mass[xy] + e.rate).max(0.0);
+                }
+            }
+        }
+
+        if curr.element_type == CELL_BLOCK {
+            self.mass[xy] = 0.0;
+            return;
+        }
+
+        let mut flow;
+        let mut remaining_mass = curr.mass;
+
+        if remaining_mass > 0.0 && self.is_open(xy_below) {
+            let below_mass = self.cell_mass(xy_below);
+            flow = self.get_vertical_flow_value(remaining_mass + below_mass) - below_mass;
+            if below_mass > 0.0 && flow > min_flow {
+                flow *= flow_speed;
+            }
+
+            flow = flow.max(0.0).min(remaining_mass.min(max_flow));
+            remaining_mass -= self.transfer(xy, xy_below, flow);
+        }
+
+        if remaining_mass > 0.0 && self.is_open(xy_left) {
+            flow = (remaining_mass - self.cell_mass(xy_left)) / 3.0;
+            if flow > min_flow {
+                flow *= flow_speed;
+            }
+
+            flow = flow.max(0.0).min(remaining_mass);
+            remaining_mass -= self.transfer(xy, xy_left, flow);
+        }
+
+        if remaining_mass > 0.0 && self.is_open(xy_right) {
+            flow = (curr.mass - self.cell_mass(xy_right)) / 3.0;
+            if flow > min_flow {
+                flow *= flow_speed;
+            }
+
+            flow = flow.max(0.0).min(remaining_mass);
+            remaining_mass -= self.transfer(xy, xy_right, flow);
+        }
+
+        if remaining_mass > 0.0 && self.is_open(xy_above) {
+            flow = remaining_mass - self.get_vertical_flow_value(remaining_mass + self.cell_mass(xy_above));
+            if flow > min_flow {
+                flow *= flow_speed;
+
+                flow = flow.max(0.0).min(remaining_mass);
+                self.transfer(xy, xy_above, flow);
+            }
+        }
+    }
+
+    /// Runs one compute dispatch
+    pub fn tick(&mut self) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.flow(x, y);
+            }
+        }
+
+        let mut next_gen = self.cells.clone();
+
+        for ((next, curr), &mass) in next_gen.iter_mut().zip(&self.cells).zip(&self.mass) {
+            if curr.element_type == CELL_EMPTY || curr.element_type == CELL_WATER {
+                let element_type = if curr.mass < self.params.min_mass { CELL_EMPTY } else { CELL_WATER };
+                *next = Cell { element_type, mass };
+            }
+        }
+
+        // Acid sinks through whatever is below it, and leaves the field at the bottom
+        for xy in 0..self.cells.len() {
+            if self.cells[xy].element_type == CELL_ACID {
+                next_gen[xy] = Cell { element_type: CELL_EMPTY, mass: 0.0 };
+                if xy >= self.width {
+                    next_gen[xy - self.width] = Cell { element_type: CELL_ACID, mass: 0.0 };
+                }
+            }
+        }
+
+        self.cells = next_gen;
+    }
+}
+
+#[test]
+fn test_water_settles_on_the_floor() {
+    use crate::ascii::Grid;
+
+    let grid: Grid = "
+        #.~.#
+        #...#
+        #####
+    ".parse().unwrap();
+    let mut sim = Reference::new(grid.width, grid.height, grid.to_cells());
+
+    for _ in 0..50 {
+        sim.tick();
+    }
+
+    let settled = Grid::from_cells(sim.width(), sim.height(), sim.cells());
+    assert_eq!(settled.to_string(), "#...#\n#333#\n#####\n");
+    assert!((sim.total_mass() - 1.0).abs() < 1e-4);
+}
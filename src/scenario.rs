@@ -0,0 +1,408 @@
+//! Declarative scenario files.
+//!
+//! A scenario is a plain-text file with one statement per line. Blank lines and
+//! lines starting with `#` are ignored. Coordinates are in cells, with the origin
+//! in the top-left corner and `y` growing downwards, just like the text grids.
+//!
+//! ```text
+//! # Dam break
+//! size 64 32
+//! seed 7
+//! generator empty
+//! param flow_speed 0.9
+//! rect block 0 31 63 31
+//! rect water 1 10 20 30
+//! circle water:0.5 40 10 3
+//! line block 30 0 30 20
+//! polygon block 45 30 55 20 63 30
+//! source 10 2 1 0.5
+//! drain 60 29 1 0.25
+//! ticks 500
+//! ```
+//!
+//! Materials are `empty`, `block`, `acid` and `water`, which may be followed by
+//! the mass of each cell as in `water:0.5`. Rectangles and lines take two corner
+//! cells, circles a centre and a radius and polygons a list of vertices. Sources
+//! and drains take a centre, a radius and the mass added or removed per tick.
+//!
+//! Masses are in full cells. Every backend scales them by its `max_mass`,
+//! after the `param` overrides, so a scenario holds as much water on both.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use rand::prng::XorShiftRng;
+use rand::{FromEntropy, SeedableRng};
+
+use crate::ascii::{Grid, ParseError, Tile, FULL_MASS};
+use crate::cave::{self, CaveSettings};
+use crate::params::Params;
+use crate::shapes;
+use crate::world::World;
+use crate::Cell;
+
+/// Fills the grid before any shape is drawn
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Generator {
+    Empty,
+    Cave,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Shape {
+    Rect { x0: i32, y0: i32, x1: i32, y1: i32 },
+    Circle { x: i32, y: i32, radius: f32 },
+    Line { x0: i32, y0: i32, x1: i32, y1: i32 },
+    Polygon { points: Vec<(i32, i32)> },
+}
+
+impl Shape {
+    pub fn cells(&self) -> Vec<(i32, i32)> {
+        match *self {
+            Shape::Rect { x0, y0, x1, y1 } => shapes::rect(x0, y0, x1, y1),
+            Shape::Circle { x, y, radius } => shapes::circle(x, y, radius),
+            Shape::Line { x0, y0, x1, y1 } => shapes::line(x0, y0, x1, y1),
+            Shape::Polygon { ref points } => shapes::polygon(points),
+        }
+    }
+}
+
+/// A source adds `rate` mass to every open cell it covers on each tick, a drain
+/// (negative `rate`) removes it.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+#[repr(C)]
+pub struct Emitter {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    pub rate: f32,
+}
+
+impl Emitter {
+    /// The cells covered by this emitter on a grid of the given size
+    pub fn cells(&self, width: usize, height: usize) -> Vec<(usize, usize)> {
+        shapes::circle(self.x.round() as i32, self.y.round() as i32, self.radius)
+            .into_iter()
+            .filter(|&(x, y)| x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height)
+            .map(|(x, y)| (x as usize, y as usize))
+            .collect()
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Scenario {
+    pub width: usize,
+    pub height: usize,
+    pub seed: Option<u64>,
+    /// Parameter overrides, applied on top of the defaults of each backend
+    pub params: Vec<(String, f32)>,
+    pub generator: Generator,
+    pub shapes: Vec<(Tile, Shape)>,
+    pub emitters: Vec<Emitter>,
+    pub ticks: Option<u64>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            width: 256,
+            height: 256,
+            seed: None,
+            params: Vec::new(),
+            generator: Generator::Empty,
+            shapes: Vec::new(),
+            emitters: Vec::new(),
+            ticks: None,
+        }
+    }
+}
+
+impl Scenario {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scenario, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+
+        Ok(text.parse()?)
+    }
+
+    /// Applies the parameter overrides of this scenario to a backend's defaults
+    pub fn params(&self, mut base: Params) -> Params {
+        for (name, value) in &self.params {
+            // Names are validated while parsing
+            base.set(name, *value).unwrap();
+        }
+
+        base
+    }
+
+    /// Runs the generator and draws every shape, in file order
+    pub fn build_grid(&self) -> Grid {
+        let mut grid = Grid::new(self.width, self.height);
+
+        if self.generator == Generator::Cave {
+            let cave_map = match self.seed {
+                Some(seed) => cave::generate(self.width, self.height, &CaveSettings::cpu(), &mut XorShiftRng::seed_from_u64(seed)),
+                None => cave::generate(self.width, self.height, &CaveSettings::cpu(), &mut XorShiftRng::from_entropy()),
+            };
+
+            for (x, column) in cave_map.iter().enumerate() {
+                for (y, &alive) in column.iter().enumerate() {
+                    if alive {
+                        grid.set(x, y, Tile::Block);
+                    }
+                }
+            }
+        }
+
+        for (tile, shape) in &self.shapes {
+            for (x, y) in shape.cells() {
+                if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+                    grid.set(x as usize, y as usize, *tile);
+                }
+            }
+        }
+
+        grid
+    }
+
+    /// Builds a CPU world, with this scenario's parameters and emitters
+    pub fn to_world(&self) -> Result<World, ParseError> {
+        let params = self.params(Params::cpu());
+        let mut world = self.build_grid().to_world_with(params)?;
+
+        world.set_emitters(self.scaled_emitters(params.max_mass));
+
+        Ok(world)
+    }
+
+    /// Builds a GPU cell buffer, first row at the bottom of the field
+    pub fn to_cells(&self) -> Vec<Cell> {
+        self.build_grid().to_cells_with(self.params(Params::gpu()))
+    }
+
+    /// The emitters in GPU field coordinates, where `y` grows upwards
+    pub fn gpu_emitters(&self) -> Vec<Emitter> {
+        let max_mass = self.params(Params::gpu()).max_mass;
+
+        self.scaled_emitters(max_mass)
+            .into_iter()
+            .map(|e| Emitter { y: (self.height - 1) as f32 - e.y, ..e })
+            .collect()
+    }
+
+    /// The emitters with their rates in the mass of a backend
+    fn scaled_emitters(&self, max_mass: f32) -> Vec<Emitter> {
+        self.emitters
+            .iter()
+            .map(|e| Emitter { rate: e.rate / FULL_MASS * max_mass, ..*e })
+            .collect()
+    }
+}
+
+fn error(line: usize, message: String) -> ParseError {
+    ParseError {
+        line,
+        column: 1,
+        message,
+    }
+}
+
+fn parse_number<T: FromStr>(line: usize, token: Option<&str>) -> Result<T, ParseError> {
+    match token {
+        Some(token) => token.parse().map_err(|_| error(line, format!("'{}' is not a valid number", token))),
+        None => Err(error(line, "missing argument".to_string())),
+    }
+}
+
+fn parse_material(line: usize, token: Option<&str>) -> Result<Tile, ParseError> {
+    let token = token.ok_or_else(|| error(line, "missing material".to_string()))?;
+    let mut parts = token.splitn(2, ':');
+
+    let tile = match parts.next().unwrap() {
+        "empty" => Tile::Empty,
+        "block" => Tile::Block,
+        "acid" => Tile::Acid,
+        "water" => match parts.next() {
+            Some(mass) => Tile::Water(parse_number(line, Some(mass))?),
+            None => Tile::Water(FULL_MASS),
+        },
+        other => return Err(error(line, format!("unknown material '{}'", other))),
+    };
+
+    if parts.next().is_some() {
+        return Err(error(line, format!("only water takes a mass, found '{}'", token)));
+    }
+
+    Ok(tile)
+}
+
+impl FromStr for Scenario {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut scenario = Scenario::default();
+
+        for (idx, raw_line) in text.lines().enumerate() {
+            let line = idx + 1;
+            let raw_line = raw_line.trim();
+
+            if raw_line.is_empty() || raw_line.starts_with('#') {
+                continue;
+            }
+
+            let mut tokens = raw_line.split_whitespace();
+            let keyword = tokens.next().unwrap();
+
+            match keyword {
+                "size" => {
+                    scenario.width = parse_number(line, tokens.next())?;
+                    scenario.height = parse_number(line, tokens.next())?;
+
+                    if scenario.width < 3 || scenario.height < 3 {
+                        return Err(error(line, "the grid must be at least 3x3".to_string()));
+                    }
+                }
+                "seed" => scenario.seed = Some(parse_number(line, tokens.next())?),
+                "generator" => {
+                    scenario.generator = match tokens.next() {
+                        Some("empty") => Generator::Empty,
+                        Some("cave") => Generator::Cave,
+                        Some(other) => return Err(error(line, format!("unknown generator '{}'", other))),
+                        None => return Err(error(line, "missing generator".to_string())),
+                    }
+                }
+                "param" => {
+                    let name = tokens.next().ok_or_else(|| error(line, "missing parameter name".to_string()))?;
+                    let value = parse_number(line, tokens.next())?;
+
+                    Params::default().set(name, value).map_err(|e| error(line, e))?;
+                    scenario.params.push((name.to_string(), value));
+                }
+                "rect" | "line" => {
+                    let tile = parse_material(line, tokens.next())?;
+                    let x0 = parse_number(line, tokens.next())?;
+                    let y0 = parse_number(line, tokens.next())?;
+                    let x1 = parse_number(line, tokens.next())?;
+                    let y1 = parse_number(line, tokens.next())?;
+
+                    let shape = if keyword == "rect" {
+                        Shape::Rect { x0, y0, x1, y1 }
+                    } else {
+                        Shape::Line { x0, y0, x1, y1 }
+                    };
+                    scenario.shapes.push((tile, shape));
+                }
+                "circle" => {
+                    let tile = parse_material(line, tokens.next())?;
+                    let x = parse_number(line, tokens.next())?;
+                    let y = parse_number(line, tokens.next())?;
+                    let radius = parse_number(line, tokens.next())?;
+
+                    scenario.shapes.push((tile, Shape::Circle { x, y, radius }));
+                }
+                "polygon" => {
+                    let tile = parse_material(line, tokens.next())?;
+                    let coords = tokens
+                        .by_ref()
+                        .map(|t| parse_number(line, Some(t)))
+                        .collect::<Result<Vec<i32>, _>>()?;
+
+                    if coords.len() < 6 || coords.len() % 2 != 0 {
+                        return Err(error(line, "a polygon needs at least three x y pairs".to_string()));
+                    }
+
+                    let points = coords.chunks(2).map(|p| (p[0], p[1])).collect();
+                    scenario.shapes.push((tile, Shape::Polygon { points }));
+                }
+                "source" | "drain" => {
+                    let x = parse_number(line, tokens.next())?;
+                    let y = parse_number(line, tokens.next())?;
+                    let radius = parse_number(line, tokens.next())?;
+                    let rate: f32 = parse_number(line, tokens.next())?;
+
+                    let rate = if keyword == "source" { rate.abs() } else { -rate.abs() };
+                    scenario.emitters.push(Emitter { x, y, radius, rate });
+                }
+                "ticks" => scenario.ticks = Some(parse_number(line, tokens.next())?),
+                other => return Err(error(line, format!("unknown statement '{}'", other))),
+            }
+
+            if let Some(extra) = tokens.next() {
+                return Err(error(line, format!("unexpected argument '{}'", extra)));
+            }
+        }
+
+        Ok(scenario)
+    }
+}
+
+#[test]
+fn test_scenario_builds_grid() {
+    let scenario: Scenario = "
+        # A small tank
+        size 6 4
+        rect block 0 3 5 3
+        line block 0 0 0 3
+        line block 5 0 5 3
+        rect water:0.5 1 2 4 2
+        source 2 0 0 0.25
+        ticks 10
+    ".parse().unwrap();
+
+    assert_eq!(scenario.ticks, Some(10));
+    assert_eq!(scenario.emitters[0].rate, 0.25);
+    assert_eq!(scenario.build_grid().to_string(), "#....#\n#....#\n#5555#\n######\n");
+}
+
+#[test]
+fn test_scenario_params_override_backend_defaults() {
+    let scenario: Scenario = "param min_flow 0.5".parse().unwrap();
+
+    assert_eq!(scenario.params(Params::cpu()).min_flow, 0.5);
+    assert_eq!(scenario.params(Params::gpu()).max_compress, Params::gpu().max_compress);
+}
+
+#[test]
+fn test_scenario_reports_line_of_error() {
+    let err = "size 8 8\nrect lava 0 0 1 1\n".parse::<Scenario>().unwrap_err();
+
+    assert_eq!(err.line, 2);
+    assert!("param gravity 1".parse::<Scenario>().is_err());
+    assert!("ticks 10 20".parse::<Scenario>().is_err());
+}
+
+#[test]
+fn test_seeded_cave_is_reproducible() {
+    let scenario: Scenario = "size 32 32\nseed 3\ngenerator cave".parse().unwrap();
+
+    assert_eq!(scenario.build_grid(), scenario.build_grid());
+}
+
+#[test]
+fn test_backends_hold_the_same_water() {
+    let scenario: Scenario = "size 6 4\nparam max_mass 2\nrect water 1 1 4 2\nsource 0 0 1 0.5".parse().unwrap();
+    let world = scenario.to_world().unwrap();
+    let cells = scenario.to_cells();
+
+    assert_eq!(world.total_mass(), 8.0 * 2.0);
+    assert_eq!(cells.iter().map(|cell| cell.mass).sum::<f32>(), 8.0 * 2.0);
+    assert_eq!(scenario.gpu_emitters()[0].rate, 1.0);
+}
+
+#[test]
+fn test_water_on_the_border_ticks() {
+    let mut world = "size 6 4\nrect water 0 0 5 0\nline water 0 0 0 3\nsource 0 3 1 1".parse::<Scenario>().unwrap().to_world().unwrap();
+
+    world.tick();
+    world.tick();
+}
+
+#[test]
+fn test_bundled_scenarios_parse() {
+    for name in &["cave_flood", "dam_break", "u_tube"] {
+        let scenario = Scenario::load(format!("scenarios/{}.scenario", name)).unwrap();
+
+        assert!(scenario.to_world().is_ok());
+    }
+}
@@ -0,0 +1,90 @@
+//! Fixed timestep of the GPU frontend.
+//!
+//! The simulation advances in updates of a fixed length, however long the
+//! frames take: every frame adds its wall time to a backlog and runs as many
+//! updates as fit into it. A frame that took long runs several updates, a
+//! short one may run none. The backlog is capped at `max_lag`, so after a
+//! stall the simulation slows down instead of running ever longer frames to
+//! catch up.
+
+use std::time::Duration;
+
+/// Default of `max_lag`, a quarter of a second of updates at most per frame
+pub const DEFAULT_MAX_LAG: Duration = Duration::from_millis(250);
+
+/// Bounds of `--rate`, an update every second at the slowest and every
+/// microsecond at the fastest, so the length of an update neither overflows a
+/// `Duration` nor rounds to zero
+pub const MIN_RATE: f64 = 1.0;
+pub const MAX_RATE: f64 = 1_000_000.0;
+
+pub struct Scheduler {
+    /// Length of an update
+    step: Duration,
+    max_lag: Duration,
+    /// Time not yet covered by updates
+    lag: Duration,
+    /// Updates skipped because the backlog went over `max_lag`
+    dropped: u64,
+}
+
+impl Scheduler {
+    /// `rate` updates per second, between `MIN_RATE` and `MAX_RATE`. The backlog
+    /// always holds at least one update.
+    pub fn new(rate: f64, max_lag: Duration) -> Self {
+        let step = Duration::from_secs_f64(1.0 / rate);
+
+        Scheduler {
+            step,
+            max_lag: max_lag.max(step),
+            lag: Duration::ZERO,
+            dropped: 0,
+        }
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Adds the wall time since the last frame and returns the updates due
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.lag += elapsed;
+
+        if self.lag > self.max_lag {
+            self.dropped += ((self.lag - self.max_lag).as_nanos() / self.step.as_nanos()) as u64;
+            self.lag = self.max_lag;
+        }
+
+        let updates = (self.lag.as_nanos() / self.step.as_nanos()) as u32;
+        self.lag -= self.step * updates;
+
+        updates
+    }
+}
+
+#[test]
+fn test_updates_follow_wall_time() {
+    let mut scheduler = Scheduler::new(400.0, DEFAULT_MAX_LAG);
+
+    // 60 frames a second, 400 updates a second
+    let updates: u32 = (0..60).map(|_| scheduler.advance(Duration::from_secs(1) / 60)).sum();
+    assert!((399..=400).contains(&updates));
+
+    // Frames faster than the updates run none most of the time
+    let mut fast = Scheduler::new(10.0, DEFAULT_MAX_LAG);
+    let updates: Vec<u32> = (0..4).map(|_| fast.advance(Duration::from_millis(40))).collect();
+    assert_eq!(updates, vec![0, 0, 1, 0]);
+}
+
+#[test]
+fn test_backlog_is_capped() {
+    let mut scheduler = Scheduler::new(100.0, Duration::from_millis(50));
+
+    assert_eq!(scheduler.advance(Duration::from_secs(2)), 5);
+    assert_eq!(scheduler.dropped(), 195);
+    assert_eq!(scheduler.advance(Duration::from_millis(10)), 1);
+}
@@ -0,0 +1,180 @@
+//! Rasterisation of simple shapes into lists of cell coordinates.
+//!
+//! Coordinates are not clipped, callers are expected to drop the cells that
+//! fall outside of their grid.
+
+/// Every cell of the axis aligned rectangle spanned by two opposite corners, inclusive
+pub fn rect(x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
+    let mut cells = Vec::new();
+
+    for y in y0.min(y1)..=y0.max(y1) {
+        for x in x0.min(x1)..=x0.max(x1) {
+            cells.push((x, y));
+        }
+    }
+
+    cells
+}
+
+/// Every cell whose centre lies within `radius` of the centre cell
+pub fn circle(cx: i32, cy: i32, radius: f32) -> Vec<(i32, i32)> {
+    let mut cells = Vec::new();
+    let r = radius.ceil() as i32;
+
+    for y in -r..=r {
+        for x in -r..=r {
+            if ((x * x + y * y) as f32) <= radius * radius {
+                cells.push((cx + x, cy + y));
+            }
+        }
+    }
+
+    cells
+}
+
+/// Every cell whose centre lies within the ellipse inscribed in the rectangle
+/// spanned by two opposite corners, inclusive
+pub fn ellipse(x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
+    let mut cells = Vec::new();
+
+    let (cx, cy) = ((x0 + x1) as f32 / 2.0, (y0 + y1) as f32 / 2.0);
+    let (rx, ry) = ((x1 - x0).abs() as f32 / 2.0 + 0.5, (y1 - y0).abs() as f32 / 2.0 + 0.5);
+
+    for y in y0.min(y1)..=y0.max(y1) {
+        for x in x0.min(x1)..=x0.max(x1) {
+            let (dx, dy) = ((x as f32 - cx) / rx, (y as f32 - cy) / ry);
+            if dx * dx + dy * dy <= 1.0 {
+                cells.push((x, y));
+            }
+        }
+    }
+
+    cells
+}
+
+/// Outline of the axis aligned rectangle spanned by two opposite corners
+pub fn rect_outline(x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
+    let mut cells = Vec::new();
+
+    cells.extend(line(x0, y0, x1, y0));
+    cells.extend(line(x0, y1, x1, y1));
+    cells.extend(line(x0, y0, x0, y1));
+    cells.extend(line(x1, y0, x1, y1));
+
+    cells.sort();
+    cells.dedup();
+
+    cells
+}
+
+/// Bresenham line between two cells, both ends included
+pub fn line(x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
+    let mut cells = Vec::new();
+
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+
+    let mut err = dx + dy;
+    let (mut x, mut y) = (x0, y0);
+
+    loop {
+        cells.push((x, y));
+
+        if x == x1 && y == y1 {
+            break;
+        }
+
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+
+    cells
+}
+
+/// Filled polygon using the even-odd rule, sampled at the centre of every cell.
+/// The outline is always included so the vertices themselves are painted.
+pub fn polygon(points: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    let mut cells = Vec::new();
+
+    if points.len() < 3 {
+        return cells;
+    }
+
+    let min_y = points.iter().map(|p| p.1).min().unwrap();
+    let max_y = points.iter().map(|p| p.1).max().unwrap();
+
+    for y in min_y..=max_y {
+        let sample_y = y as f32 + 0.5;
+        let mut crossings = Vec::new();
+
+        for i in 0..points.len() {
+            let (ax, ay) = points[i];
+            let (bx, by) = points[(i + 1) % points.len()];
+            let (ay, by) = (ay as f32 + 0.5, by as f32 + 0.5);
+
+            if (ay <= sample_y && by > sample_y) || (by <= sample_y && ay > sample_y) {
+                let t = (sample_y - ay) / (by - ay);
+                crossings.push(ax as f32 + 0.5 + t * (bx - ax) as f32);
+            }
+        }
+
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for pair in crossings.chunks(2) {
+            if let [start, end] = *pair {
+                let first = (start - 0.5).ceil() as i32;
+                let last = (end - 0.5).floor() as i32;
+                for x in first..=last {
+                    cells.push((x, y));
+                }
+            }
+        }
+    }
+
+    for i in 0..points.len() {
+        let (ax, ay) = points[i];
+        let (bx, by) = points[(i + 1) % points.len()];
+        cells.extend(line(ax, ay, bx, by));
+    }
+
+    cells.sort();
+    cells.dedup();
+
+    cells
+}
+
+#[test]
+fn test_line_includes_both_ends() {
+    let cells = line(0, 0, 4, 2);
+
+    assert_eq!(cells.first(), Some(&(0, 0)));
+    assert_eq!(cells.last(), Some(&(4, 2)));
+    assert_eq!(cells.len(), 5);
+}
+
+#[test]
+fn test_ellipse_fits_its_rectangle() {
+    let cells = ellipse(0, 0, 6, 2);
+
+    assert!(cells.contains(&(0, 1)) && cells.contains(&(6, 1)));
+    assert!(cells.contains(&(3, 0)) && cells.contains(&(3, 2)));
+    assert!(!cells.contains(&(0, 0)) && !cells.contains(&(6, 2)));
+}
+
+#[test]
+fn test_polygon_matches_rect() {
+    let square = polygon(&[(0, 0), (3, 0), (3, 3), (0, 3)]);
+    let mut expected = rect(0, 0, 3, 3);
+    expected.sort();
+
+    assert_eq!(square, expected);
+}
@@ -0,0 +1,139 @@
+//! A common interface over the two implementations of the flow rules, so the
+//! tools that drive a simulation without a window work with either backend.
+
+use std::error::Error;
+use std::path::Path;
+
+use crate::ascii::Grid;
+use crate::image;
+use crate::params::Params;
+use crate::raster;
+use crate::reference::Reference;
+use crate::scenario::Scenario;
+use crate::world::World;
+use crate::Backend;
+
+pub trait Simulation {
+    fn tick(&mut self);
+
+    fn set_params(&mut self, params: Params);
+
+    fn total_mass(&self) -> f32;
+
+    /// Width and height in cells
+    fn size(&self) -> (usize, usize);
+
+    /// Mass of one cell, `y` grows downwards like in the text grid
+    fn mass(&self, x: usize, y: usize) -> f32;
+
+    /// The current state as a text grid, first row at the top of the field
+    fn to_grid(&self) -> Grid;
+
+    /// The current state as drawn by its frontend, one `0xRRGGBB` pixel per
+    /// cell and the top row first
+    fn render(&self) -> Vec<u32>;
+}
+
+impl Simulation for World {
+    fn tick(&mut self) {
+        World::tick(self)
+    }
+
+    fn set_params(&mut self, params: Params) {
+        World::set_params(self, params)
+    }
+
+    fn total_mass(&self) -> f32 {
+        World::total_mass(self)
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.width(), self.height())
+    }
+
+    fn mass(&self, x: usize, y: usize) -> f32 {
+        World::mass(self, x, y)
+    }
+
+    fn to_grid(&self) -> Grid {
+        Grid::from_world(self)
+    }
+
+    fn render(&self) -> Vec<u32> {
+        let mut buff = vec![0; self.width() * self.height()];
+        World::render(self, &mut buff);
+
+        buff
+    }
+}
+
+impl Simulation for Reference {
+    fn tick(&mut self) {
+        Reference::tick(self)
+    }
+
+    fn set_params(&mut self, params: Params) {
+        Reference::set_params(self, params)
+    }
+
+    fn total_mass(&self) -> f32 {
+        Reference::total_mass(self)
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.width(), self.height())
+    }
+
+    fn mass(&self, x: usize, y: usize) -> f32 {
+        self.masses()[x + (self.height() - 1 - y) * self.width()]
+    }
+
+    fn to_grid(&self) -> Grid {
+        Grid::from_cells(self.width(), self.height(), self.cells())
+    }
+
+    fn render(&self) -> Vec<u32> {
+        raster::render_cells(self.width(), self.height(), self.cells())
+    }
+}
+
+/// Saves the current state as a PNG file, with `scale` by `scale` pixels per cell
+pub fn save_image(simulation: &dyn Simulation, path: &Path, scale: usize) -> Result<(), Box<dyn Error>> {
+    let (width, height) = simulation.size();
+    let pixels = image::scale(&simulation.render(), width, scale);
+
+    image::save_png(path, width * scale, height * scale, &pixels)
+}
+
+/// Builds a simulation of `grid`, the GPU backend runs the shader's reference logic
+pub fn from_grid(backend: Backend, grid: &Grid) -> Result<Box<dyn Simulation>, Box<dyn Error>> {
+    Ok(match backend {
+        Backend::Cpu => Box::new(grid.to_world()?),
+        Backend::Gpu => Box::new(Reference::new(grid.width, grid.height, grid.to_cells())),
+    })
+}
+
+/// Builds a simulation of `grid` running with `params` instead of the backend's defaults
+pub fn from_grid_with(backend: Backend, grid: &Grid, params: Params) -> Result<Box<dyn Simulation>, Box<dyn Error>> {
+    Ok(match backend {
+        Backend::Cpu => Box::new(grid.to_world_with(params)?),
+        Backend::Gpu => {
+            let mut reference = Reference::new(grid.width, grid.height, grid.to_cells_with(params));
+            reference.set_params(params);
+            Box::new(reference)
+        }
+    })
+}
+
+/// Builds a simulation of `scenario`, with its parameters and emitters
+pub fn from_scenario(backend: Backend, scenario: &Scenario) -> Result<Box<dyn Simulation>, Box<dyn Error>> {
+    Ok(match backend {
+        Backend::Cpu => Box::new(scenario.to_world()?),
+        Backend::Gpu => {
+            let mut reference = Reference::new(scenario.width, scenario.height, scenario.to_cells());
+            reference.set_params(scenario.params(Params::gpu()));
+            reference.set_emitters(scenario.gpu_emitters());
+            Box::new(reference)
+        }
+    })
+}
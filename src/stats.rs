@@ -0,0 +1,33 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// One sample of the simulation state
+#[derive(Copy, Clone, Debug)]
+pub struct Stats {
+    pub tick: u64,
+    pub tick_time: Duration,
+    pub total_mass: f32,
+}
+
+/// Writes samples as CSV rows, one per line
+pub struct StatsWriter {
+    out: BufWriter<File>,
+}
+
+impl StatsWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<StatsWriter, Box<dyn Error>> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "tick,tick_time_us,total_mass")?;
+
+        Ok(StatsWriter { out })
+    }
+
+    pub fn write(&mut self, stats: &Stats) -> Result<(), Box<dyn Error>> {
+        writeln!(self.out, "{},{},{}", stats.tick, stats.tick_time.as_micros(), stats.total_mass)?;
+
+        Ok(())
+    }
+}
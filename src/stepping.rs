@@ -0,0 +1,164 @@
+//! Pausing, stepping and speed controls shared by both frontends.
+//!
+//! A frontend asks the `Stepper` how many ticks to run before it renders the
+//! next frame. Running, that is the ticks per frame times the speed, with the
+//! fractions carried over so speeds below one run a tick every few frames.
+//! Paused, it is only the steps queued since the last frame. The GPU frontend
+//! asks for the ticks of the updates its `Scheduler` has due instead.
+
+use std::time::Duration;
+
+pub const MIN_SPEED: f32 = 1.0 / 8.0;
+pub const MAX_SPEED: f32 = 16.0;
+/// Highest ticks per frame the keys go up to, `--ticks-per-frame` may start above it
+pub const MAX_TICKS_PER_FRAME: u32 = 64;
+/// Ticks queued by the step N binding
+pub const STEP_COUNT: u32 = 10;
+/// Time fast-forward spends running ticks between two rendered frames, short
+/// enough for the window to keep handling input
+pub const FAST_FORWARD_FRAME: Duration = Duration::from_millis(200);
+
+pub struct Stepper {
+    paused: bool,
+    /// Steps queued while paused, run with the next frame
+    pending: u32,
+    ticks_per_frame: u32,
+    speed: f32,
+    /// Fraction of a tick left over from the frames before
+    carry: f32,
+    fast_forward: bool,
+}
+
+impl Stepper {
+    pub fn new(ticks_per_frame: u32) -> Self {
+        Stepper {
+            paused: false,
+            pending: 0,
+            ticks_per_frame: ticks_per_frame.max(1),
+            speed: 1.0,
+            carry: 0.0,
+            fast_forward: false,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Resuming drops steps that have not run yet
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.pending = 0;
+        self.carry = 0.0;
+    }
+
+    /// Pauses and queues `count` ticks for the next frame
+    pub fn step(&mut self, count: u32) {
+        self.paused = true;
+        self.pending = self.pending.saturating_add(count);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(MAX_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.0).max(MIN_SPEED);
+    }
+
+    pub fn ticks_per_frame(&self) -> u32 {
+        self.ticks_per_frame
+    }
+
+    pub fn more_ticks_per_frame(&mut self) {
+        if self.ticks_per_frame < MAX_TICKS_PER_FRAME {
+            self.ticks_per_frame += 1;
+        }
+    }
+
+    pub fn fewer_ticks_per_frame(&mut self) {
+        self.ticks_per_frame = (self.ticks_per_frame - 1).max(1);
+    }
+
+    /// Whether ticks should keep running for `FAST_FORWARD_FRAME` before the
+    /// next frame is rendered. Pausing stops fast-forward too.
+    pub fn fast_forward(&self) -> bool {
+        self.fast_forward && !self.paused
+    }
+
+    pub fn toggle_fast_forward(&mut self) {
+        self.fast_forward = !self.fast_forward;
+    }
+
+    /// Ticks to run before the next frame is rendered
+    pub fn ticks_for_frame(&mut self) -> u32 {
+        self.ticks_for_updates(1)
+    }
+
+    /// Ticks for `updates` of a fixed timestep, each of them runs the ticks
+    /// of a frame. Paused, the queued steps run whether an update is due or not.
+    pub fn ticks_for_updates(&mut self, updates: u32) -> u32 {
+        if self.paused {
+            return std::mem::take(&mut self.pending);
+        }
+
+        self.carry += (updates * self.ticks_per_frame) as f32 * self.speed;
+        let ticks = self.carry.floor();
+        self.carry -= ticks;
+
+        ticks as u32
+    }
+
+    /// The speed and ticks per frame for the HUD
+    pub fn status(&self) -> String {
+        if self.fast_forward() {
+            "FAST FORWARD".to_string()
+        } else {
+            format!("SPEED X{} {} TPF", self.speed, self.ticks_per_frame)
+        }
+    }
+}
+
+#[test]
+fn test_speed_carries_fractions_of_ticks() {
+    let mut stepper = Stepper::new(3);
+    stepper.slower();
+    stepper.slower();
+
+    // 0.75 ticks per frame
+    let ticks: Vec<u32> = (0..4).map(|_| stepper.ticks_for_frame()).collect();
+    assert_eq!(ticks, vec![0, 1, 1, 1]);
+
+    for _ in 0..10 {
+        stepper.faster();
+    }
+    assert_eq!(stepper.speed(), MAX_SPEED);
+    assert_eq!(stepper.ticks_for_frame(), 48);
+}
+
+#[test]
+fn test_steps_only_run_while_paused() {
+    let mut stepper = Stepper::new(2);
+
+    stepper.step(1);
+    stepper.step(STEP_COUNT);
+    assert!(stepper.is_paused());
+    assert_eq!(stepper.ticks_for_frame(), 11);
+    assert_eq!(stepper.ticks_for_frame(), 0);
+
+    stepper.step(5);
+    stepper.toggle_fast_forward();
+    assert!(!stepper.fast_forward());
+    stepper.toggle_pause();
+    assert!(stepper.fast_forward());
+    assert_eq!(stepper.ticks_for_frame(), 2);
+    assert_eq!(stepper.ticks_for_updates(3), 6);
+}
@@ -0,0 +1,185 @@
+//! Painting tools shared by both frontends.
+//!
+//! The brush and the eraser paint wherever the cursor is while the button is
+//! held. The shapes are dragged out from the cell where the button went down
+//! and painted once it is released. Flood fill applies as soon as the button
+//! goes down. The frontends map their own keys to the tools.
+//!
+//! The brush either paints the selected element or, in one of its flow modes,
+//! pours or drains water at a fixed rate for every tick it is held down. A flow
+//! covers the cells around the cursor only, so the mass it moves is exactly
+//! the rate times the cells covered times the ticks.
+
+use std::collections::HashSet;
+
+use crate::shapes;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Tool {
+    Brush,
+    Line,
+    Rect,
+    FilledRect,
+    Ellipse,
+    Fill,
+    /// Removes blocks and water alike
+    Eraser,
+}
+
+pub const MIN_RADIUS: f32 = 0.0;
+pub const MAX_RADIUS: f32 = 20.0;
+
+/// What the brush does to the cells it covers
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BrushMode {
+    /// Overwrites them with the selected element
+    Paint,
+    /// Adds water on every tick
+    Pour,
+    /// Removes water on every tick, down to empty cells
+    Drain,
+}
+
+/// Flow rates are in full cells of water per cell and tick
+pub const DEFAULT_FLOW_RATE: f32 = 0.05;
+pub const MIN_FLOW_RATE: f32 = 0.001;
+pub const MAX_FLOW_RATE: f32 = 1.0;
+
+impl BrushMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            BrushMode::Paint => "paint",
+            BrushMode::Pour => "pour",
+            BrushMode::Drain => "drain",
+        }
+    }
+
+    /// The mode the brush switches to when cycling through them
+    pub fn next(self) -> Self {
+        match self {
+            BrushMode::Paint => BrushMode::Pour,
+            BrushMode::Pour => BrushMode::Drain,
+            BrushMode::Drain => BrushMode::Paint,
+        }
+    }
+
+    /// Mass added to every covered cell per tick, negative when draining. A
+    /// full cell holds `max_mass`.
+    pub fn flow(self, rate: f32, max_mass: f32) -> f32 {
+        match self {
+            BrushMode::Paint => 0.0,
+            BrushMode::Pour => rate * max_mass,
+            BrushMode::Drain => -rate * max_mass,
+        }
+    }
+}
+
+impl Tool {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Brush => "brush",
+            Tool::Line => "line",
+            Tool::Rect => "rectangle",
+            Tool::FilledRect => "filled rectangle",
+            Tool::Ellipse => "ellipse",
+            Tool::Fill => "fill",
+            Tool::Eraser => "eraser",
+        }
+    }
+
+    /// Whether the tool paints on every frame while the button is held
+    pub fn is_continuous(self) -> bool {
+        self == Tool::Brush || self == Tool::Eraser
+    }
+
+    /// Whether the tool is dragged out and painted when the button is released
+    pub fn is_shape(self) -> bool {
+        !self.is_continuous() && self != Tool::Fill
+    }
+
+    /// The cells painted by a drag from `start` to `end`. For the brush and the
+    /// eraser, `start` is the cursor of the previous frame, so a fast stroke
+    /// has no gaps. Those and lines are `radius` thick, flood fill has no cells
+    /// of its own.
+    pub fn cells(self, start: (i32, i32), end: (i32, i32), radius: f32) -> Vec<(i32, i32)> {
+        let (x0, y0) = start;
+        let (x1, y1) = end;
+
+        match self {
+            Tool::Brush | Tool::Eraser | Tool::Line => thicken(&shapes::line(x0, y0, x1, y1), radius),
+            Tool::Rect => shapes::rect_outline(x0, y0, x1, y1),
+            Tool::FilledRect => shapes::rect(x0, y0, x1, y1),
+            Tool::Ellipse => shapes::ellipse(x0, y0, x1, y1),
+            Tool::Fill => Vec::new(),
+        }
+    }
+}
+
+/// Every cell within `radius` of one of `cells`
+fn thicken(cells: &[(i32, i32)], radius: f32) -> Vec<(i32, i32)> {
+    let mut thick: Vec<(i32, i32)> = cells
+        .iter()
+        .flat_map(|&(x, y)| shapes::circle(x, y, radius))
+        .collect();
+
+    thick.sort();
+    thick.dedup();
+
+    thick
+}
+
+/// The 4-connected region around `start` of the cells for which `same` holds
+pub fn flood_fill<F: Fn(usize, usize) -> bool>(
+    width: usize,
+    height: usize,
+    start: (usize, usize),
+    same: F,
+) -> Vec<(usize, usize)> {
+    let mut region = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![start];
+
+    while let Some((x, y)) = stack.pop() {
+        if x >= width || y >= height || !seen.insert((x, y)) || !same(x, y) {
+            continue;
+        }
+
+        region.push((x, y));
+
+        stack.push((x + 1, y));
+        stack.push((x, y + 1));
+        if x > 0 {
+            stack.push((x - 1, y));
+        }
+        if y > 0 {
+            stack.push((x, y - 1));
+        }
+    }
+
+    region
+}
+
+#[test]
+fn test_brush_stroke_has_no_gaps() {
+    let cells = Tool::Brush.cells((0, 0), (10, 0), 0.0);
+
+    assert_eq!(cells, (0..=10).map(|x| (x, 0)).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_brush_modes_flow_in_full_cells() {
+    assert_eq!(BrushMode::Paint.flow(0.5, 10.0), 0.0);
+    assert_eq!(BrushMode::Pour.flow(0.5, 10.0), 5.0);
+    assert_eq!(BrushMode::Drain.flow(0.5, 10.0), -5.0);
+    assert_eq!(BrushMode::Drain.next().next().next(), BrushMode::Drain);
+}
+
+#[test]
+fn test_flood_fill_stops_at_walls() {
+    // A wall splits a 5x3 grid into a 2 wide and a 2 wide region
+    let wall = |x: usize, _: usize| x == 2;
+    let region = flood_fill(5, 3, (0, 0), |x, y| !wall(x, y));
+
+    assert_eq!(region.len(), 6);
+    assert!(region.iter().all(|&(x, _)| x < 2));
+}
@@ -0,0 +1,282 @@
+//! Measures how close the flow rules come to hydrostatics.
+//!
+//! Two experiments run for every parameter preset on both backends:
+//!
+//! - A U-tube, two vessels connected at the bottom and filled through the left
+//!   one. Once the water rests, both surfaces should be level.
+//! - A tall column with the water spread evenly. Once it rests, the mass of a
+//!   cell should grow linearly with its depth, by `max_compress` per cell.
+//!
+//! Masses are measured in units of `max_mass`, the mass of a full uncompressed
+//! cell of the preset.
+
+use std::error::Error;
+
+use crate::ascii::{Grid, Tile, FULL_MASS};
+use crate::params::Params;
+use crate::simulation::{self, Simulation};
+use crate::Backend;
+
+/// A run stops once no cell changes by more than this over a tick, in units of `max_mass`
+const SETTLED_DELTA: f32 = 1e-4;
+
+const TICK_BUDGET: u64 = 20_000;
+
+const U_TUBE_WIDTH: usize = 25;
+const U_TUBE_HEIGHT: usize = 40;
+/// Rows of the channel under the wall between the arms
+const U_TUBE_CHANNEL: usize = 4;
+
+const COLUMN_HEIGHT: usize = 64;
+/// Rows of water in the column once it is settled, if the model was ideal
+const COLUMN_ROWS: usize = 56;
+
+pub struct UTube {
+    /// Mean height of the water in each arm, in cells
+    pub left_level: f32,
+    pub right_level: f32,
+    /// Ticks until the water came to rest, `None` if it never did within the budget
+    pub settled_after: Option<u64>,
+}
+
+impl UTube {
+    /// Zero for level surfaces
+    pub fn level_difference(&self) -> f32 {
+        (self.left_level - self.right_level).abs()
+    }
+}
+
+pub struct Column {
+    /// Mass of every cell with water in it, top to bottom, in units of `max_mass`
+    pub masses: Vec<f32>,
+    /// `max_compress` in units of `max_mass`
+    pub ideal_slope: f32,
+    /// Rows of water once it is settled, if the model was ideal
+    pub rows: usize,
+    pub settled_after: Option<u64>,
+}
+
+impl Column {
+    /// Least squares slope of mass against depth
+    pub fn slope(&self) -> f32 {
+        let depths: Vec<f32> = (0..self.masses.len()).map(|d| d as f32).collect();
+
+        linear_fit(&depths, &self.masses).0
+    }
+
+    /// Largest difference between a cell and the cell at the same height in the
+    /// ideal profile, which has `rows` full cells growing by `ideal_slope`
+    /// per cell of depth. Heights count from the floor, so a partly filled
+    /// surface cell does not shift the whole profile.
+    pub fn max_error(&self) -> f32 {
+        self.masses
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(height, mass)| {
+                let ideal = match self.rows.checked_sub(height + 1) {
+                    Some(depth) => 1.0 + depth as f32 * self.ideal_slope,
+                    None => 0.0,
+                };
+                (mass - ideal).abs()
+            })
+            .fold(0.0, f32::max)
+    }
+}
+
+pub struct Report {
+    pub preset: &'static str,
+    pub backend: Backend,
+    pub u_tube: UTube,
+    pub column: Column,
+}
+
+fn linear_fit(xs: &[f32], ys: &[f32]) -> (f32, f32) {
+    let n = xs.len() as f32;
+    let mean_x = xs.iter().sum::<f32>() / n;
+    let mean_y = ys.iter().sum::<f32>() / n;
+
+    let covariance: f32 = xs.iter().zip(ys).map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f32 = xs.iter().map(|x| (x - mean_x) * (x - mean_x)).sum();
+
+    if variance == 0.0 {
+        return (0.0, mean_y);
+    }
+
+    let slope = covariance / variance;
+    (slope, mean_y - slope * mean_x)
+}
+
+/// A grid walled in on every side
+fn closed_grid(width: usize, height: usize) -> Grid {
+    let mut grid = Grid::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                grid.set(x, y, Tile::Block);
+            }
+        }
+    }
+
+    grid
+}
+
+/// Ticks until no cell changes by more than `SETTLED_DELTA`, `None` if that takes longer than the budget
+fn settle(sim: &mut dyn Simulation, width: usize, height: usize, max_mass: f32) -> Option<u64> {
+    let masses = |sim: &dyn Simulation| -> Vec<f32> {
+        (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| sim.mass(x, y)).collect()
+    };
+    let mut previous = masses(sim);
+
+    for tick in 1..=TICK_BUDGET {
+        sim.tick();
+
+        let current = masses(sim);
+        let delta = previous.iter().zip(&current).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+        if delta < SETTLED_DELTA * max_mass {
+            return Some(tick);
+        }
+        previous = current;
+    }
+
+    None
+}
+
+pub fn u_tube(backend: Backend, params: Params) -> Result<UTube, Box<dyn Error>> {
+    u_tube_sized(backend, params, (U_TUBE_WIDTH, U_TUBE_HEIGHT))
+}
+
+fn u_tube_sized(backend: Backend, params: Params, (width, height): (usize, usize)) -> Result<UTube, Box<dyn Error>> {
+    let wall = width / 2 - 1..=width / 2 + 1;
+    let mut grid = closed_grid(width, height);
+
+    for y in 1..height - 1 - U_TUBE_CHANNEL {
+        for x in wall.clone() {
+            grid.set(x, y, Tile::Block);
+        }
+    }
+
+    // Fill the upper part of the left arm only
+    for y in height / 4..height - 1 - U_TUBE_CHANNEL {
+        for x in 1..*wall.start() {
+            grid.set(x, y, Tile::Water(FULL_MASS));
+        }
+    }
+
+    let mut sim = simulation::from_grid_with(backend, &grid, params)?;
+    let settled_after = settle(sim.as_mut(), width, height, params.max_mass);
+
+    let level = |columns: std::ops::Range<usize>| -> f32 {
+        let count = columns.len() as f32;
+        let filled: f32 = columns
+            .flat_map(|x| (0..height).map(move |y| (x, y)))
+            .map(|(x, y)| (sim.mass(x, y) / params.max_mass).min(1.0))
+            .sum();
+
+        filled / count
+    };
+
+    Ok(UTube {
+        left_level: level(1..*wall.start()),
+        right_level: level(wall.end() + 1..width - 1),
+        settled_after,
+    })
+}
+
+pub fn column(backend: Backend, params: Params) -> Result<Column, Box<dyn Error>> {
+    column_sized(backend, params, COLUMN_HEIGHT, COLUMN_ROWS)
+}
+
+/// A column `height` cells high, holding the water of `rows` ideal cells
+fn column_sized(backend: Backend, params: Params, height: usize, rows: usize) -> Result<Column, Box<dyn Error>> {
+    let width = 3;
+    let ideal_slope = params.max_compress / params.max_mass;
+    let mut grid = closed_grid(width, height);
+
+    // Exactly the mass of the ideal profile, spread evenly over the same rows
+    let total: f32 = (0..rows).map(|depth| 1.0 + depth as f32 * ideal_slope).sum();
+    let per_cell = total / rows as f32 * FULL_MASS;
+    for y in height - 1 - rows..height - 1 {
+        grid.set(1, y, Tile::Water(per_cell));
+    }
+
+    let mut sim = simulation::from_grid_with(backend, &grid, params)?;
+    let settled_after = settle(sim.as_mut(), width, height, params.max_mass);
+
+    let masses = (1..height - 1)
+        .map(|y| sim.mass(1, y))
+        .filter(|&mass| mass >= params.min_mass)
+        .map(|mass| mass / params.max_mass)
+        .collect();
+
+    Ok(Column {
+        masses,
+        ideal_slope,
+        rows,
+        settled_after,
+    })
+}
+
+/// Runs both experiments for every preset on both backends
+pub fn run() -> Result<Vec<Report>, Box<dyn Error>> {
+    let mut reports = Vec::new();
+
+    for &preset in Params::PRESETS {
+        let params = Params::preset(preset).unwrap();
+
+        for &backend in &[Backend::Cpu, Backend::Gpu] {
+            reports.push(Report {
+                preset,
+                backend,
+                u_tube: u_tube(backend, params)?,
+                column: column(backend, params)?,
+            });
+        }
+    }
+
+    Ok(reports)
+}
+
+#[test]
+fn test_linear_fit() {
+    let (slope, intercept) = linear_fit(&[0.0, 1.0, 2.0, 3.0], &[1.0, 1.5, 2.0, 2.5]);
+
+    assert!((slope - 0.5).abs() < 1e-6);
+    assert!((intercept - 1.0).abs() < 1e-6);
+}
+
+/// Presets whose U-tube does not level on both backends, `--validate` reports
+/// how far apart their arms stay
+#[cfg(test)]
+const UNLEVEL_PRESETS: &[&str] = &["cpu", "tutorial"];
+
+/// Largest column error of any preset on the small grid, in units of `max_mass`
+#[cfg(test)]
+const COLUMN_TOLERANCE: f32 = 0.2;
+
+#[test]
+fn test_presets_come_close_to_hydrostatics() {
+    for &preset in Params::PRESETS {
+        let params = Params::preset(preset).unwrap();
+
+        for &backend in &[Backend::Cpu, Backend::Gpu] {
+            let u_tube = u_tube_sized(backend, params, (13, 30)).unwrap();
+            let column = column_sized(backend, params, 40, 32).unwrap();
+
+            if !UNLEVEL_PRESETS.contains(&preset) {
+                assert!(u_tube.settled_after.is_some(), "{} on the {}: the U-tube never settled", preset, backend);
+                assert!(
+                    u_tube.level_difference() < 0.1,
+                    "{} on the {}: the U-tube levels differ by {}", preset, backend, u_tube.level_difference(),
+                );
+            }
+
+            assert!(column.settled_after.is_some(), "{} on the {}: the column never settled", preset, backend);
+            assert!(
+                column.max_error() < COLUMN_TOLERANCE,
+                "{} on the {}: the column is off by {}", preset, backend, column.max_error(),
+            );
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Maps the window onto the field.
+//!
+//! The field is scaled as large as the framebuffer allows without stretching its
+//! cells, the bars left over on either side stay black. Cursor positions arrive
+//! in window coordinates with the origin at the top left, which are not
+//! framebuffer pixels on HiDPI screens. Field coordinates count cells from the
+//! lower left corner, like the compute shader does.
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Viewport {
+    /// Lower left corner of the field in framebuffer pixels, as `glViewport` expects it
+    pub x: i32,
+    pub y: i32,
+    /// Size of the field in framebuffer pixels
+    pub width: i32,
+    pub height: i32,
+    framebuffer_height: i32,
+    /// Framebuffer pixels per window coordinate along each axis
+    pixel_ratio: (f32, f32),
+    field_width: i32,
+    field_height: i32,
+}
+
+impl Viewport {
+    /// The largest centered rectangle of the field's aspect ratio that fits the framebuffer
+    pub fn fit(window: (i32, i32), framebuffer: (i32, i32), field: (i32, i32)) -> Self {
+        // A minimized window has no framebuffer, keep everything at least one pixel large
+        let (window_width, window_height) = (window.0.max(1), window.1.max(1));
+        let (framebuffer_width, framebuffer_height) = (framebuffer.0.max(1), framebuffer.1.max(1));
+        let (field_width, field_height) = (field.0.max(1), field.1.max(1));
+
+        let scale = (framebuffer_width as f32 / field_width as f32).min(framebuffer_height as f32 / field_height as f32);
+        let width = ((field_width as f32 * scale).round() as i32).clamp(1, framebuffer_width);
+        let height = ((field_height as f32 * scale).round() as i32).clamp(1, framebuffer_height);
+
+        Viewport {
+            x: (framebuffer_width - width) / 2,
+            y: (framebuffer_height - height) / 2,
+            width,
+            height,
+            framebuffer_height,
+            pixel_ratio: (
+                framebuffer_width as f32 / window_width as f32,
+                framebuffer_height as f32 / window_height as f32,
+            ),
+            field_width,
+            field_height,
+        }
+    }
+
+    /// Position under the window coordinates `(x, y)` in fractions of the
+    /// field's rectangle, from its lower left corner. It lies outside of 0 to 1
+    /// when the cursor is over the black bars.
+    pub fn window_to_view(&self, x: f32, y: f32) -> (f32, f32) {
+        let pixel_x = x * self.pixel_ratio.0;
+        // Framebuffer rows count from the bottom
+        let pixel_y = self.framebuffer_height as f32 - y * self.pixel_ratio.1;
+
+        (
+            (pixel_x - self.x as f32) / self.width as f32,
+            (pixel_y - self.y as f32) / self.height as f32,
+        )
+    }
+
+    /// Position in the whole field under the window coordinates `(x, y)`
+    pub fn window_to_field(&self, x: f32, y: f32) -> (f32, f32) {
+        let (view_x, view_y) = self.window_to_view(x, y);
+
+        (view_x * self.field_width as f32, view_y * self.field_height as f32)
+    }
+
+    /// The cell under the window coordinates `(x, y)`
+    pub fn window_to_cell(&self, x: f32, y: f32) -> (i32, i32) {
+        let (field_x, field_y) = self.window_to_field(x, y);
+
+        (field_x.floor() as i32, field_y.floor() as i32)
+    }
+}
+
+#[test]
+fn test_letterboxed_corners_map_to_field_corners() {
+    // A 2:1 field in a square window gets bars above and below
+    let viewport = Viewport::fit((400, 400), (400, 400), (200, 100));
+
+    assert_eq!((viewport.x, viewport.y, viewport.width, viewport.height), (0, 100, 400, 200));
+    assert_eq!(viewport.window_to_cell(0.0, 299.0), (0, 0));
+    assert_eq!(viewport.window_to_cell(399.0, 101.0), (199, 99));
+    assert!(viewport.window_to_field(200.0, 50.0).1 > 100.0);
+}
+
+#[test]
+fn test_hidpi_cursor_uses_window_coordinates() {
+    // The framebuffer has twice the pixels of the window it belongs to
+    let viewport = Viewport::fit((300, 200), (600, 400), (100, 100));
+
+    assert_eq!((viewport.x, viewport.width), (100, 400));
+    assert_eq!(viewport.window_to_cell(150.0, 100.0), (50, 50));
+    assert_eq!(viewport.window_to_cell(50.0, 199.0), (0, 0));
+}
@@ -0,0 +1,176 @@
+//! Debug views shared by both frontends.
+//!
+//! Besides the normal rendering, the field can be shown as a heatmap of the
+//! mass, of the compression above `max_mass`, as flat cell types or as the net
+//! flow of every cell during the last tick. The colour ramps here are mirrored
+//! in `composition.frag`, so both renderers read the same way.
+
+use crate::hud::{self, BACKGROUND, FOREGROUND, GLYPH_HEIGHT};
+use crate::params::Params;
+use crate::world::{Cell, Color};
+
+/// Depth of water, in cells, that tops the pressure scale. Every cell of water
+/// stacked above another adds about `max_compress` to its mass.
+pub const PRESSURE_DEPTH: f32 = 64.0;
+
+/// Solid cells in the heatmaps, so they stand apart from empty ones
+pub const SOLID: u32 = 0x606060;
+
+/// Colours of the heatmap, evenly spaced from the low to the high end
+const HEAT: [u32; 5] = [0x000000, 0x2020c0, 0x00c0c0, 0xe0e000, 0xff2020];
+
+const GAIN: u32 = 0x4080ff;
+const LOSS: u32 = 0xff4040;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(i32)]
+pub enum View {
+    Normal = 0,
+    Mass = 1,
+    Pressure = 2,
+    Type = 3,
+    Flow = 4,
+}
+
+impl View {
+    pub fn name(self) -> &'static str {
+        match self {
+            View::Normal => "normal",
+            View::Mass => "mass",
+            View::Pressure => "pressure",
+            View::Type => "type",
+            View::Flow => "flow",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            View::Normal => View::Mass,
+            View::Mass => View::Pressure,
+            View::Pressure => View::Type,
+            View::Type => View::Flow,
+            View::Flow => View::Normal,
+        }
+    }
+
+    /// Values at the two ends of the legend. The mass and pressure views show
+    /// the mass of a cell, the flow view the mass it gained in the last tick.
+    pub fn range(self, params: &Params) -> Option<(f32, f32)> {
+        match self {
+            View::Normal | View::Type => None,
+            View::Mass => Some((0.0, params.max_mass)),
+            View::Pressure => Some((params.max_mass, params.max_mass + PRESSURE_DEPTH * params.max_compress)),
+            View::Flow => {
+                // A cell can not move more than it holds, nor faster than the vertical limit
+                let limit = params.max_speed.min(params.max_mass);
+                Some((-limit, limit))
+            }
+        }
+    }
+
+    /// Colour of a cell holding `value`, for the views with a range
+    pub fn color(self, value: f32, (low, high): (f32, f32)) -> u32 {
+        let t = ((value - low) / (high - low)).clamp(0.0, 1.0);
+
+        match self {
+            View::Flow => diverging(2.0 * t - 1.0),
+            _ => heatmap(t),
+        }
+    }
+}
+
+/// Blends two `0xRRGGBB` colours, `t` of 0 gives `a` and 1 gives `b`
+pub fn mix(a: u32, b: u32, t: f32) -> u32 {
+    let channel = |shift: u32| {
+        let (a, b) = (((a >> shift) & 0xff) as f32, ((b >> shift) & 0xff) as f32);
+        ((a + (b - a) * t).round() as u32) << shift
+    };
+
+    channel(16) | channel(8) | channel(0)
+}
+
+/// Heatmap colour of `t` from 0 to 1
+pub fn heatmap(t: f32) -> u32 {
+    let scaled = t.clamp(0.0, 1.0) * (HEAT.len() - 1) as f32;
+    let i = (scaled.floor() as usize).min(HEAT.len() - 2);
+
+    mix(HEAT[i], HEAT[i + 1], scaled - i as f32)
+}
+
+/// Black at 0, fading to red for losses down to -1 and to blue for gains up to 1
+pub fn diverging(t: f32) -> u32 {
+    let t = t.clamp(-1.0, 1.0);
+
+    if t < 0.0 {
+        mix(0, LOSS, -t)
+    } else {
+        mix(0, GAIN, t)
+    }
+}
+
+/// Colour of the flat cell type view
+pub fn type_color(cell: Cell) -> u32 {
+    match cell {
+        Cell::Air => Color::Black.get_hex(),
+        Cell::Water => Color::Blue.get_hex(),
+        Cell::Ground => Color::Desert.get_hex(),
+    }
+}
+
+const PADDING: usize = 2;
+const BAR_HEIGHT: usize = 4;
+
+pub const LEGEND_WIDTH: usize = hud::WIDTH;
+pub const LEGEND_HEIGHT: usize = 2 * PADDING + 2 * GLYPH_HEIGHT + BAR_HEIGHT + 2;
+
+/// The legend of a view with a range: its name, the colour ramp and the
+/// values at both ends, as an image of `LEGEND_WIDTH` by `LEGEND_HEIGHT` pixels
+pub fn legend(view: View, (low, high): (f32, f32)) -> Vec<u32> {
+    let mut image = vec![BACKGROUND; LEGEND_WIDTH * LEGEND_HEIGHT];
+
+    hud::draw_text(&mut image, LEGEND_WIDTH, (PADDING, PADDING), view.name(), FOREGROUND);
+
+    let bar_top = PADDING + GLYPH_HEIGHT + 1;
+    let bar_width = LEGEND_WIDTH - 2 * PADDING;
+    for x in 0..bar_width {
+        let value = low + (high - low) * x as f32 / (bar_width - 1) as f32;
+        let color = view.color(value, (low, high));
+
+        for y in bar_top..bar_top + BAR_HEIGHT {
+            image[PADDING + x + y * LEGEND_WIDTH] = color;
+        }
+    }
+
+    let labels_top = bar_top + BAR_HEIGHT + 1;
+    let (low, high) = (format!("{:.2}", low), format!("{:.2}", high));
+    hud::draw_text(&mut image, LEGEND_WIDTH, (PADDING, labels_top), &low, FOREGROUND);
+    let high_x = LEGEND_WIDTH - PADDING - hud::text_width(&high);
+    hud::draw_text(&mut image, LEGEND_WIDTH, (high_x, labels_top), &high, FOREGROUND);
+
+    image
+}
+
+#[test]
+fn test_ramps_hit_their_ends() {
+    assert_eq!(heatmap(0.0), 0x000000);
+    assert_eq!(heatmap(1.0), 0xff2020);
+    assert_eq!(heatmap(0.5), 0x00c0c0);
+    assert_eq!(diverging(0.0), 0x000000);
+    assert_eq!(diverging(-1.0), LOSS);
+    assert_eq!(diverging(1.0), GAIN);
+
+    let range = View::Flow.range(&Params::cpu()).unwrap();
+    assert_eq!(View::Flow.color(0.0, range), 0x000000);
+    assert_eq!(View::Mass.color(-5.0, (0.0, 1.0)), 0x000000);
+}
+
+#[test]
+fn test_legend_fills_its_image() {
+    let view = View::Pressure;
+    let image = legend(view, view.range(&Params::cpu()).unwrap());
+
+    assert_eq!(image.len(), LEGEND_WIDTH * LEGEND_HEIGHT);
+    // The right end of the bar has the hottest colour
+    let bar_row = PADDING + GLYPH_HEIGHT + 1;
+    assert_eq!(image[LEGEND_WIDTH - PADDING - 1 + bar_row * LEGEND_WIDTH], heatmap(1.0));
+}
@@ -0,0 +1,693 @@
+use std::fmt;
+
+use rand::prng::XorShiftRng;
+use rand::{FromEntropy, SeedableRng};
+
+use crate::ascii::Grid;
+use crate::cave::{self, CaveSettings};
+use crate::params::Params;
+use crate::scenario::Emitter;
+use crate::tools;
+use crate::views::{self, View};
+
+pub const MIN_FLOW: f32 = 0.01;
+pub const MAX_MASS: f32 = 10.0;
+pub const MAX_COMPRESS: f32 = 0.02;
+pub const MIN_MASS: f32 = 0.0001;
+pub const MIN_DRAW: f32 = 0.01;
+pub const MAX_DRAW: f32 = 1.1;
+pub const MAX_SPEED: f32 = 1.0;
+
+#[derive(Clone, PartialEq)]
+pub enum Color {
+    Black,
+    White,
+    Red,
+    Yellow,
+    Green,
+    Cyan,
+    Purple,
+    Blue,
+    Desert,
+}
+
+impl Color {
+    pub fn get_hex(&self) -> u32 {
+        use Color::*;
+
+        match self {
+            Black => 0x000000,
+            White => 0xffffff,
+            Red => 0xff0000,
+            Yellow => 0xffff00,
+            Green => 0x00ff00,
+            Cyan => 0x00ffff,
+            Blue => 0x0000ff,
+            Purple => 0xff00ff,
+            Desert => 0xccae62,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialOrd, PartialEq, Debug)]
+pub enum Cell {
+    Water,
+    Ground,
+    Air,
+}
+
+impl Cell {
+    fn empty() -> Self {
+        Cell::Air
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Cell::Water => "water",
+            Cell::Ground => "ground",
+            Cell::Air => "air",
+        }
+    }
+}
+
+pub struct World {
+    width: usize,
+    height: usize,
+
+    water: Box<Vec<i32>>,
+    energy: Box<Vec<i32>>,
+    ground: Box<Vec<i32>>,
+
+    pub(crate) mass: Box<Vec<Vec<f32>>>,
+    new_mass: Box<Vec<Vec<f32>>>,
+    /// Mass every cell gained (or lost, if negative) by flowing in the last tick
+    net_flow: Vec<Vec<f32>>,
+    pub(crate) blocks: Box<Vec<Vec<Cell>>>,
+
+    selected_element: Cell,
+
+    params: Params,
+    emitters: Vec<Emitter>,
+    rng: XorShiftRng,
+}
+
+pub fn clamp<T: PartialOrd>(val: T, min: T, max: T) -> T {
+    if val < min {
+        min
+    } else {
+        if val > max {
+            max
+        } else {
+            val
+        }
+    }
+}
+
+/// Part of a pixel covered by the water of a cell filled to `fill`, with the
+/// water resting on the bottom of the cell. `top` and `bottom` are the edges of
+/// the pixel, in cells from the top of the cell.
+pub fn surface_coverage(fill: f32, top: f32, bottom: f32) -> f32 {
+    let (top, bottom) = (top.max(0.0), bottom.min(1.0));
+    if bottom <= top {
+        return 0.0;
+    }
+
+    let water_top = (1.0 - fill).max(top);
+
+    ((bottom - water_top) / (bottom - top)).clamp(0.0, 1.0)
+}
+
+pub fn lerp_range(x: f32, in_min: f32, in_max: f32, out_min: f32, out_max: f32) -> f32 {
+    (x - in_min) * (out_max - out_min) / (in_max - in_min) + out_min
+}
+
+#[test]
+fn test_lerp() {
+    assert_eq!(lerp_range(5.0, 0.0, 10.0, 0.0, 100.0), 50.0);
+}
+
+impl World {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut this = Self {
+            width,
+            height,
+
+            water: Box::new(vec![0; width]),
+            energy: Box::new(vec![0; width]),
+            ground: Box::new(vec![0; width]),
+
+            mass: Box::new(vec![vec![0.0; height]; width]),
+            new_mass: Box::new(vec![vec![0.0; height]; width]),
+            net_flow: vec![vec![0.0; height]; width],
+            blocks: Box::new(vec![vec![Cell::empty(); height]; width]),
+
+            selected_element: Cell::Ground,
+
+            params: Params::cpu(),
+            emitters: Vec::new(),
+            rng: XorShiftRng::from_entropy(),
+        };
+
+        this.select_element(Cell::Ground);
+
+        this
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, params: Params) {
+        self.params = params;
+    }
+
+    /// Makes the procedural maps reproducible
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = XorShiftRng::seed_from_u64(seed);
+    }
+
+    pub fn total_mass(&self) -> f32 {
+        self.mass.iter().flat_map(|column| column.iter()).sum()
+    }
+
+    pub fn mass(&self, x: usize, y: usize) -> f32 {
+        self.mass[x][y]
+    }
+
+    pub fn net_flow(&self, x: usize, y: usize) -> f32 {
+        self.net_flow[x][y]
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Cell {
+        self.blocks[x][y]
+    }
+
+    /// Sources and drains applied at the start of every tick
+    pub fn set_emitters(&mut self, emitters: Vec<Emitter>) {
+        self.emitters = emitters;
+    }
+
+    /// Adds (or with a negative amount removes) water in a cell. Ground cells are left untouched
+    /// and the mass never drops below zero.
+    pub fn add_mass(&mut self, x: usize, y: usize, amount: f32) {
+        if self.blocks[x][y] == Cell::Ground {
+            return;
+        }
+
+        let mass = (self.mass[x][y] + amount).max(0.0);
+        self.mass[x][y] = mass;
+        self.new_mass[x][y] = mass;
+
+        if mass > self.params.min_mass {
+            self.blocks[x][y] = Cell::Water;
+        }
+    }
+
+    fn apply_emitters(&mut self) {
+        let emitters = self.emitters.clone();
+
+        for e in emitters {
+            for (x, y) in e.cells(self.width, self.height) {
+                self.add_mass(x, y, e.rate);
+            }
+        }
+    }
+
+    fn get_stable_state(&self, total_mass: f32) -> f32 {
+        let max_mass = self.params.max_mass;
+        let max_compress = self.params.max_compress;
+
+        if total_mass <= 1.0 {
+            1.0
+        } else if total_mass < 2.0 * max_mass + max_compress {
+            (max_mass.powi(2) + total_mass * max_compress) / (max_mass + max_compress)
+        } else {
+            (total_mass + max_compress) / 2.0
+        }
+    }
+
+    pub fn clear_map(&mut self) {
+        self.water = Box::new(vec![0; self.width]);
+        self.energy = Box::new(vec![0; self.width]);
+        self.ground = Box::new(vec![0; self.width]);
+        self.mass = Box::new(vec![vec![0.0; self.height]; self.width]);
+        self.new_mass = Box::new(vec![vec![0.0; self.height]; self.width]);
+        self.net_flow = vec![vec![0.0; self.height]; self.width];
+        self.blocks = Box::new(vec![vec![Cell::empty(); self.height]; self.width]);
+    }
+
+    pub fn tick(&mut self) {
+        self.apply_emitters();
+
+        let Params { min_mass, min_flow, max_speed, flow_speed, .. } = self.params;
+        let mut flow = 0.0;
+        let mut blocks = self.blocks.clone();
+        let mass = self.mass.clone();
+        let mut new_mass = self.new_mass.clone();
+        // let mut new_mass = [[0.0; WIDTH]; HEIGHT];
+        let mut remaining_mass;
+
+        // Calculate and apply flow for each block
+        for x in 0..self.width - 1 {
+            for y in 0..self.height - 1 {
+                // Skip inert ground blocks
+                if blocks[x][y] == Cell::Ground {
+                    continue;
+                }
+
+                // Custom push-only flow
+                flow = 0.0;
+                remaining_mass = mass[x][y];
+
+                if remaining_mass <= 0.0 {
+                    continue;
+                }
+
+                // The block below this one
+                if blocks[x][y + 1] != Cell::Ground {
+                    flow = self.get_stable_state(remaining_mass + mass[x][y + 1]) - mass[x][y + 1];
+                    if flow > min_flow {
+                        flow *= flow_speed; // leads to smoother flow
+                    }
+
+                    flow = clamp(flow, 0.0, remaining_mass.min(max_speed));
+
+                    new_mass[x][y] -= flow;
+                    new_mass[x][y + 1] += flow;
+                    remaining_mass -= flow;
+                }
+
+                if remaining_mass <= 0.0 {
+                    continue;
+                }
+
+                // Left, cells outside of the grid count as ground like in the shader
+                if x > 0 && blocks[x - 1][y] != Cell::Ground {
+                    // Equalize the amount of water in this block and its neighbor
+                    flow = (mass[x][y] - mass[x - 1][y]) / 4.0;
+                    if flow > min_flow {
+                        flow *= flow_speed;
+                    }
+                    flow = clamp(flow, 0.0, remaining_mass);
+
+                    new_mass[x][y] -= flow;
+                    new_mass[x - 1][y] += flow;
+                    remaining_mass -= flow;
+                }
+
+                if remaining_mass <= 0.0 {
+                    continue;
+                }
+
+                // Right
+                if blocks[x + 1][y] != Cell::Ground {
+                    flow = (mass[x][y] - mass[x + 1][y]) / 4.0;
+                    if flow > min_flow {
+                        flow *= flow_speed;
+                    }
+
+                    flow = clamp(flow, 0.0, remaining_mass);
+
+                    new_mass[x][y] -= flow;
+                    new_mass[x + 1][y] += flow;
+                    remaining_mass -= flow;
+                }
+
+                if remaining_mass <= 0.0 {
+                    continue;
+                }
+
+                // Up. Only compressed water flows upwards
+                if y > 0 && blocks[x][y - 1] != Cell::Ground {
+                    flow = remaining_mass - self.get_stable_state(remaining_mass + mass[x][y - 1]);
+                    if flow >= min_flow {
+                        flow *= flow_speed;
+                    }
+
+                    flow = clamp(flow, 0.0, remaining_mass.min(max_speed));
+
+                    new_mass[x][y] -= flow;
+                    new_mass[x][y - 1] += flow;
+                    remaining_mass -= flow;
+                }
+            }
+        }
+
+        for x in 0..self.width {
+            for y in 0..self.height {
+                // Skip ground blocks
+                if blocks[x][y] == Cell::Ground {
+                    continue;
+                }
+
+                // Flag/unflag water blocks
+                if mass[x][y] > min_mass {
+                    blocks[x][y] = Cell::Water;
+                } else {
+                    blocks[x][y] = Cell::Air;
+                }
+            }
+        }
+
+        // remove any water that has left the map
+        for x in 0..self.width {
+            new_mass[x][0] = 0.0;
+            new_mass[x][self.height - 1] = 0.0;
+        }
+
+        for y in 0..self.height {
+            new_mass[0][y] = 0.0;
+            new_mass[self.width - 1][y] = 0.0;
+        }
+
+        for x in 0..self.width {
+            for y in 0..self.height {
+                self.net_flow[x][y] = new_mass[x][y] - mass[x][y];
+            }
+        }
+
+        self.mass = new_mass.clone();
+        self.new_mass = new_mass.clone();
+        self.blocks = blocks;
+    }
+
+    /// Paints `element` over every cell in `cells`, dropping the ones outside of the grid.
+    /// Water fills a cell to `max_mass`, air erases blocks and water alike.
+    pub fn paint(&mut self, cells: &[(i32, i32)], element: Cell) {
+        for &(x, y) in cells {
+            if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                continue;
+            }
+            let (x, y) = (x as usize, y as usize);
+
+            let mass = match element {
+                Cell::Water => self.params.max_mass,
+                Cell::Ground | Cell::Air => 0.0,
+            };
+
+            self.blocks[x][y] = element;
+            self.mass[x][y] = mass;
+            self.new_mass[x][y] = mass;
+        }
+    }
+
+    /// Adds `amount` of water to each of `cells`, or removes it if negative.
+    /// Cells outside of the world are skipped.
+    pub fn pour(&mut self, cells: &[(i32, i32)], amount: f32) {
+        for &(x, y) in cells {
+            if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                continue;
+            }
+
+            self.add_mass(x as usize, y as usize, amount);
+        }
+    }
+
+    /// Paints `element` over the region connected to `(x, y)` that holds the same kind of cell
+    pub fn flood_fill(&mut self, x: usize, y: usize, element: Cell) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        let target = self.blocks[x][y];
+        let region = tools::flood_fill(self.width, self.height, (x, y), |x, y| self.blocks[x][y] == target);
+        let cells: Vec<(i32, i32)> = region.into_iter().map(|(x, y)| (x as i32, y as i32)).collect();
+
+        self.paint(&cells, element);
+    }
+
+    fn get_water_color(&self, mut mass: f32) -> u32 {
+        mass = clamp(mass, MIN_MASS, MAX_MASS);
+        let mut g = 50.0;
+        let mut r = 50.0;
+        let mut b;
+
+        if (mass < 1.0) {
+            b = lerp_range(mass, 0.01, 1.0, 255.0, 200.0);
+            r = lerp_range(mass, 0.01, 1.0, 240.0, 50.0);
+            r = clamp(r, 50.0, 240.0);
+            g = r;
+        } else {
+            b = lerp_range(mass, 1.0, 1.1, 90.0, 140.0);
+        }
+
+        (1 << 24) + ((r as u32) << 16) + ((g as u32) << 8) + b as u32
+    }
+
+    pub fn render(&self, buff: &mut [u32]) {
+        self.render_simulation(buff);
+    }
+
+    /// How full a water cell at the surface is, from 0 to 1. Cells that are
+    /// full or have anything but air above them are drawn whole and give `None`.
+    pub fn surface_fill(&self, x: usize, y: usize) -> Option<f32> {
+        let open_above = y == 0 || self.blocks[x][y - 1] == Cell::Air;
+        let fill = self.mass[x][y] / self.params.max_mass;
+
+        if self.blocks[x][y] != Cell::Water || !open_above || fill >= 1.0 {
+            return None;
+        }
+
+        Some(fill.max(0.0))
+    }
+
+    /// Colour of a pixel of the smooth water surface, centred on `position` and
+    /// `size` large, both in cells. `frame` is the world drawn by `render`, partly
+    /// filled cells at the surface only hold water up to the height of their mass.
+    pub fn smooth_pixel(&self, frame: &[u32], (x, y): (f32, f32), (_, pixel_height): (f32, f32)) -> u32 {
+        let cell_x = (x.floor().max(0.0) as usize).min(self.width - 1);
+        let cell_y = (y.floor().max(0.0) as usize).min(self.height - 1);
+        let color = frame[cell_x + cell_y * self.width];
+
+        match self.surface_fill(cell_x, cell_y) {
+            Some(fill) => {
+                let centre = y - cell_y as f32;
+                let coverage = surface_coverage(fill, centre - pixel_height / 2.0, centre + pixel_height / 2.0);
+
+                views::mix(Color::Black.get_hex(), color, coverage)
+            }
+            None => color,
+        }
+    }
+
+    /// Renders the world through one of the debug views
+    pub fn render_view(&self, buff: &mut [u32], view: View) {
+        let range = match view.range(&self.params) {
+            Some(range) => range,
+            None if view == View::Type => (0.0, 1.0),
+            None => return self.render_simulation(buff),
+        };
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = self.blocks[x][y];
+
+                buff[y * self.width + x] = match view {
+                    View::Type => views::type_color(cell),
+                    _ if cell == Cell::Ground => views::SOLID,
+                    View::Flow => view.color(self.net_flow[x][y], range),
+                    _ => view.color(self.mass[x][y], range),
+                }
+            }
+        }
+    }
+
+    /// The colour an element is rendered with, water as a cell of mass 1
+    pub fn element_color(&self, element: Cell) -> u32 {
+        match element {
+            Cell::Water => self.get_water_color(1.0),
+            Cell::Air => Color::Black.get_hex(),
+            Cell::Ground => Color::Desert.get_hex(),
+        }
+    }
+
+    fn render_simulation(&self, buff: &mut [u32]) {
+        let mass = self.mass.clone();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let current_cell = self.blocks[x][y];
+
+                buff[y * self.width + x] = match current_cell {
+                    // Cell::Water => Color::Blue.get_hex(),
+                    Cell::Water => self.get_water_color(mass[x][y]),
+                    // Cell::Water => Color::Red.get_hex(),
+                    Cell::Air => Color::Black.get_hex(),
+                    Cell::Ground => Color::Desert.get_hex(),
+                    _ => 0,
+                }
+            }
+        }
+    }
+
+    pub fn selected_element(&self) -> Cell {
+        self.selected_element
+    }
+
+    pub fn select_element(&mut self, cell_element: Cell) {
+        self.selected_element = cell_element;
+    }
+
+    pub fn rotate_canvas_anticlockwise(&mut self) {
+        // Rotating in place only works on a square canvas
+        if self.width != self.height {
+            return;
+        }
+
+        let n = self.height;
+        let mut blocks = self.blocks.clone();
+
+        // Processing each block one by one
+        for i in 0..n / 2 {
+
+            // Processing elements in group of 4 in the current square
+            for j in i..n - i - 1 {
+                // Storing current cell in a temporal variable
+                let tmp_block = blocks[i][j];
+
+                // Move values from right to top
+                blocks[i][j] = blocks[j][n - 1 - i];
+
+                // Move values from bottom to right
+                blocks[j][n - 1 - i] = blocks[n - 1 - i][n - 1 - j];
+
+                // Move values from left to bottom
+                blocks[n - 1 - i][n - 1 - j] = blocks[n - 1 - j][i];
+
+                // Assign temporal to left
+                blocks[n - 1 - j][i] = tmp_block;
+            }
+        }
+
+        self.blocks = blocks;
+    }
+
+    pub fn rotate_canvas_clockwise(&mut self) {
+        // Rotating in place only works on a square canvas
+        if self.width != self.height {
+            return;
+        }
+
+        let n = self.height;
+        let mut blocks = self.blocks.clone();
+
+        // Traverse each cycle
+        for i in 0..n / 2 {
+            for j in i..n - i - 1 {
+                // Swap elements of each cycle in clockwise direction
+                let tmp_block = blocks[i][j];
+                blocks[i][j] = blocks[n - 1 - j][i];
+                blocks[n - 1 - j][i] = blocks[n - 1 - i][n - 1 - j];
+                blocks[n - 1 - i][n - 1 - j] = blocks[j][n - 1 - i];
+                blocks[j][n - 1 - i] = tmp_block;
+            }
+        }
+
+        self.blocks = blocks;
+    }
+
+    pub fn generate_map(&mut self) {
+        self.clear_map();
+        let mut blocks = self.blocks.clone();
+        let cave_map = cave::generate(self.width, self.height, &CaveSettings::cpu(), &mut self.rng);
+
+        for i in 0..self.width {
+            for j in 0..self.height {
+                if cave_map[i][j] {
+                    blocks[i][j] = Cell::Ground;
+                } else {
+                    blocks[i][j] = Cell::Air;
+                }
+            }
+        }
+
+        self.blocks = blocks;
+    }
+}
+
+impl fmt::Display for World {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Grid::from_world(self).fmt(f)
+    }
+}
+
+#[test]
+fn test_water_falls_to_the_floor() {
+    let mut world = crate::ascii::parse_world("
+        #####
+        #.~.#
+        #...#
+        #...#
+        #####
+    ").unwrap();
+
+    for _ in 0..50 {
+        world.tick();
+    }
+
+    assert_eq!(world.to_string(), "#####\n#...#\n#...#\n#333#\n#####\n");
+}
+
+#[test]
+fn test_surface_coverage() {
+    // A pixel as large as the cell blends by how full it is
+    assert_eq!(surface_coverage(0.25, 0.0, 1.0), 0.25);
+    assert_eq!(surface_coverage(0.25, -1.0, 2.0), 0.25);
+    // Smaller pixels are either above or below the water line, or cut by it
+    assert_eq!(surface_coverage(0.25, 0.1, 0.2), 0.0);
+    assert_eq!(surface_coverage(0.25, 0.8, 0.9), 1.0);
+    assert_eq!(surface_coverage(0.5, 0.25, 0.75), 0.5);
+}
+
+#[test]
+fn test_only_the_surface_is_partly_filled() {
+    let mut world = World::new(3, 3);
+    world.paint(&[(1, 1), (1, 2)], Cell::Water);
+    world.add_mass(1, 1, -world.params().max_mass / 2.0);
+    world.add_mass(1, 2, -world.params().max_mass / 2.0);
+
+    assert_eq!(world.surface_fill(1, 1), Some(0.5));
+    // Water above, drawn as a whole cell
+    assert_eq!(world.surface_fill(1, 2), None);
+    assert_eq!(world.surface_fill(0, 0), None);
+}
+
+#[test]
+fn test_net_flow_balances_in_a_closed_box() {
+    let mut world = crate::ascii::parse_world("
+        #####
+        #~..#
+        #...#
+        #####
+    ").unwrap();
+
+    world.tick();
+
+    let flows: Vec<f32> = (0..5).flat_map(|x| (0..4).map(move |y| (x, y))).map(|(x, y)| world.net_flow(x, y)).collect();
+    assert!(world.net_flow(1, 1) < 0.0);
+    assert!(flows.iter().sum::<f32>().abs() < 1e-5);
+}
+
+#[test]
+fn test_pour_moves_exact_amounts() {
+    let mut world = World::new(5, 5);
+    let cells = [(1, 1), (2, 1), (-1, 0), (5, 5)];
+
+    world.pour(&cells, 2.5);
+    assert_eq!(world.total_mass(), 5.0);
+
+    world.pour(&cells, -2.0);
+    assert_eq!(world.total_mass(), 1.0);
+
+    // Draining never leaves negative mass behind
+    world.pour(&cells, -2.0);
+    assert_eq!(world.total_mass(), 0.0);
+}
@@ -0,0 +1,147 @@
+//! Golden-state regression tests.
+//!
+//! Every case runs a bundled scenario for a fixed number of ticks on both
+//! backends and compares the mass of every cell with `tests/golden/`. After an
+//! intended change of the flow rules, regenerate the files with
+//!
+//! ```text
+//! BLESS=1 cargo test --test golden
+//! ```
+//!
+//! A golden file holds one line per row of the field, top row first. Cells are
+//! separated by spaces: `#` is a block, `%` acid, and anything else the mass.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use automata_sandbox::ascii::Tile;
+use automata_sandbox::scenario::Scenario;
+use automata_sandbox::simulation::{self, Simulation};
+use automata_sandbox::Backend;
+
+struct Case {
+    name: &'static str,
+    ticks: u64,
+}
+
+/// Scenarios from `scenarios/`, each of them has a fixed layout or seed. The
+/// U-tube runs for the 5000 ticks of its scenario. The GPU rules level it
+/// after about 3500, the CPU ones never do: their arms are still 8 cells
+/// apart after 200 000 ticks, so its golden pins the CPU where the scenario
+/// stops.
+const CASES: &[Case] = &[
+    Case { name: "dam_break", ticks: 300 },
+    Case { name: "u_tube", ticks: 5000 },
+    Case { name: "cave_flood", ticks: 300 },
+];
+
+/// Largest difference of a cell's mass that is not a regression
+const TOLERANCE: f32 = 1e-3;
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn format_state(sim: &dyn Simulation) -> String {
+    let grid = sim.to_grid();
+    let mut out = String::new();
+
+    for y in 0..grid.height {
+        let row: Vec<String> = (0..grid.width)
+            .map(|x| match grid.get(x, y) {
+                Tile::Block => "#".to_string(),
+                Tile::Acid => "%".to_string(),
+                _ if sim.mass(x, y) == 0.0 => "0".to_string(),
+                _ => format!("{:.4}", sim.mass(x, y)),
+            })
+            .collect();
+
+        out.push_str(&row.join(" "));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Describes the first cell that differs by more than `TOLERANCE`, if any
+fn compare_states(expected: &str, actual: &str) -> Option<String> {
+    let expected_rows: Vec<&str> = expected.lines().collect();
+    let actual_rows: Vec<&str> = actual.lines().collect();
+
+    if expected_rows.len() != actual_rows.len() {
+        return Some(format!("expected {} rows, got {}", expected_rows.len(), actual_rows.len()));
+    }
+
+    for (y, (expected_row, actual_row)) in expected_rows.iter().zip(&actual_rows).enumerate() {
+        let expected_cells: Vec<&str> = expected_row.split_whitespace().collect();
+        let actual_cells: Vec<&str> = actual_row.split_whitespace().collect();
+
+        if expected_cells.len() != actual_cells.len() {
+            return Some(format!("row {}: expected {} cells, got {}", y, expected_cells.len(), actual_cells.len()));
+        }
+
+        for (x, (a, b)) in expected_cells.iter().zip(&actual_cells).enumerate() {
+            let matches = match (a.parse::<f32>(), b.parse::<f32>()) {
+                (Ok(a), Ok(b)) => (a - b).abs() <= TOLERANCE,
+                _ => a == b,
+            };
+
+            if !matches {
+                return Some(format!("cell ({}, {}): expected {}, got {}", x, y, a, b));
+            }
+        }
+    }
+
+    None
+}
+
+#[test]
+fn test_golden_states() {
+    let bless = env::var_os("BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for case in CASES {
+        let scenario = Scenario::load(root().join(format!("scenarios/{}.scenario", case.name))).unwrap();
+
+        for &backend in &[Backend::Cpu, Backend::Gpu] {
+            let mut sim = simulation::from_scenario(backend, &scenario).unwrap();
+            for _ in 0..case.ticks {
+                sim.tick();
+            }
+
+            let state = format_state(sim.as_ref());
+            let path = root().join(format!("tests/golden/{}_{}.txt", case.name, backend));
+
+            if bless {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, state).unwrap();
+                continue;
+            }
+
+            match fs::read_to_string(&path) {
+                Ok(expected) => {
+                    if let Some(difference) = compare_states(&expected, &state) {
+                        failures.push(format!("{} on the {}: {}", case.name, backend, difference));
+                    }
+                }
+                Err(err) => failures.push(format!("{}: {}", path.display(), err)),
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "the simulation no longer matches its golden states, run `BLESS=1 cargo test --test golden` \
+         if the change is intended:\n{}",
+        failures.join("\n"),
+    );
+}
+
+#[test]
+fn test_compare_states_tolerance() {
+    assert_eq!(compare_states("# 0.5000 0\n", "# 0.5004 0\n"), None);
+    assert!(compare_states("# 0.5000 0\n", "# 0.5100 0\n").is_some());
+    assert!(compare_states("# 0.5000 0\n", "0 0.5000 0\n").is_some());
+    assert!(compare_states("# 0\n", "# 0\n# 0\n").is_some());
+}
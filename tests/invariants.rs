@@ -0,0 +1,168 @@
+//! Physical invariants of the flow rules, checked on randomized grids for both
+//! `World::tick` and the reference port of the compute shader.
+//!
+//! Every grid is walled in by blocks. The CPU world removes any water that
+//! reaches its outer ring, so the walls are what turns that drain off.
+
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+
+use automata_sandbox::ascii::{Grid, Tile};
+use automata_sandbox::params::Params;
+use automata_sandbox::simulation::{self, Simulation};
+use automata_sandbox::Backend;
+
+/// Random grids per property and backend
+const CASES: u64 = 32;
+
+const TICKS: usize = 200;
+
+/// Ticks a closed container gets to come to rest
+const SETTLE_BUDGET: usize = 10_000;
+
+/// Largest change of any cell's mass over one tick that still counts as
+/// resting, in full cells
+const SETTLED_DELTA: f32 = 1e-3;
+
+/// A walled grid of blocks, water of up to twice the normal mass, and empty cells
+fn random_grid(seed: u64) -> Grid {
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+    let width = rng.gen_range(5, 24);
+    let height = rng.gen_range(5, 24);
+    let mut grid = Grid::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let wall = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+            let roll: f32 = rng.gen();
+
+            let tile = if wall || roll < 0.15 {
+                Tile::Block
+            } else if roll < 0.6 {
+                Tile::Water(rng.gen_range(0.0, 2.0))
+            } else {
+                Tile::Empty
+            };
+            grid.set(x, y, tile);
+        }
+    }
+
+    grid
+}
+
+fn cases() -> impl Iterator<Item = (Backend, u64, Grid)> {
+    [Backend::Cpu, Backend::Gpu]
+        .iter()
+        .flat_map(|&backend| (0..CASES).map(move |seed| (backend, seed, random_grid(seed))))
+}
+
+fn masses(sim: &dyn Simulation, grid: &Grid) -> Vec<f32> {
+    let mut masses = Vec::with_capacity(grid.width * grid.height);
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            masses.push(sim.mass(x, y));
+        }
+    }
+
+    masses
+}
+
+fn blocks(grid: &Grid) -> Vec<bool> {
+    let mut blocks = Vec::with_capacity(grid.width * grid.height);
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            blocks.push(grid.get(x, y) == Tile::Block);
+        }
+    }
+
+    blocks
+}
+
+#[test]
+fn test_mass_is_never_negative_or_nan() {
+    for (backend, seed, grid) in cases() {
+        let mut sim = simulation::from_grid(backend, &grid).unwrap();
+
+        for tick in 0..TICKS {
+            sim.tick();
+
+            for (i, mass) in masses(sim.as_ref(), &grid).into_iter().enumerate() {
+                assert!(
+                    mass >= 0.0,
+                    "{} seed {}: cell {} has mass {} after {} ticks", backend, seed, i, mass, tick + 1,
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_total_mass_is_conserved() {
+    for (backend, seed, grid) in cases() {
+        let mut sim = simulation::from_grid(backend, &grid).unwrap();
+        let initial = sim.total_mass();
+
+        for _ in 0..TICKS {
+            sim.tick();
+        }
+
+        let total = sim.total_mass();
+        assert!(
+            (total - initial).abs() <= 1e-4 * initial.max(1.0),
+            "{} seed {}: total mass went from {} to {}", backend, seed, initial, total,
+        );
+    }
+}
+
+#[test]
+fn test_blocks_never_change() {
+    for (backend, seed, grid) in cases() {
+        let mut sim = simulation::from_grid(backend, &grid).unwrap();
+        let initial = blocks(&grid);
+
+        for tick in 0..TICKS {
+            sim.tick();
+
+            assert!(
+                blocks(&sim.to_grid()) == initial,
+                "{} seed {}: the blocks changed after {} ticks", backend, seed, tick + 1,
+            );
+        }
+    }
+}
+
+#[test]
+fn test_closed_container_comes_to_rest() {
+    for (backend, seed, grid) in cases() {
+        let mut sim = simulation::from_grid(backend, &grid).unwrap();
+        let mut previous = masses(sim.as_ref(), &grid);
+        let mut delta = f32::INFINITY;
+        let full = match backend {
+            Backend::Cpu => Params::cpu().max_mass,
+            Backend::Gpu => Params::gpu().max_mass,
+        };
+
+        for _ in 0..SETTLE_BUDGET {
+            sim.tick();
+
+            let current = masses(sim.as_ref(), &grid);
+            delta = previous
+                .iter()
+                .zip(&current)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f32::max);
+            previous = current;
+
+            if delta < SETTLED_DELTA * full {
+                break;
+            }
+        }
+
+        assert!(
+            delta < SETTLED_DELTA * full,
+            "{} seed {}: cells still change by {} after {} ticks", backend, seed, delta / full, SETTLE_BUDGET,
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Painting at the edges and corners of the world, with every tool. Whatever
+//! part of a stroke falls outside is clipped.
+
+use automata_sandbox::tools::Tool;
+use automata_sandbox::world::{Cell, World};
+
+const WIDTH: usize = 24;
+const HEIGHT: usize = 16;
+
+/// Wide enough for every stroke along an edge to reach past it
+const RADIUS: f32 = 4.0;
+
+const TOOLS: &[Tool] = &[
+    Tool::Brush,
+    Tool::Line,
+    Tool::Rect,
+    Tool::FilledRect,
+    Tool::Ellipse,
+    Tool::Fill,
+    Tool::Eraser,
+];
+
+/// Every corner, the middle of every edge, and points just past them
+fn edge_points() -> Vec<(i32, i32)> {
+    let (right, bottom) = (WIDTH as i32 - 1, HEIGHT as i32 - 1);
+    let (middle_x, middle_y) = (WIDTH as i32 / 2, HEIGHT as i32 / 2);
+
+    vec![
+        (0, 0),
+        (right, 0),
+        (0, bottom),
+        (right, bottom),
+        (middle_x, 0),
+        (middle_x, bottom),
+        (0, middle_y),
+        (right, middle_y),
+        (-1, -1),
+        (right + 1, bottom + 1),
+        (-5, middle_y),
+        (middle_x, bottom + 5),
+    ]
+}
+
+fn inside((x, y): (i32, i32)) -> bool {
+    x >= 0 && y >= 0 && (x as usize) < WIDTH && (y as usize) < HEIGHT
+}
+
+#[test]
+fn test_every_tool_clips_at_edges_and_corners() {
+    for &tool in TOOLS {
+        for &start in &edge_points() {
+            for &end in &edge_points() {
+                let mut world = World::new(WIDTH, HEIGHT);
+                let cells = tool.cells(start, end, RADIUS);
+
+                world.paint(&cells, Cell::Ground);
+
+                for &cell in cells.iter().filter(|&&cell| inside(cell)) {
+                    assert_eq!(
+                        world.cell(cell.0 as usize, cell.1 as usize), Cell::Ground,
+                        "{} from {:?} to {:?} missed {:?}", tool.name(), start, end, cell,
+                    );
+                }
+
+                // Water on the outermost cells must not reach past the grid
+                let mut world = World::new(WIDTH, HEIGHT);
+                world.paint(&cells, Cell::Water);
+                world.tick();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_pouring_clips_at_edges_and_corners() {
+    for &point in &edge_points() {
+        let mut world = World::new(WIDTH, HEIGHT);
+        let cells = Tool::Brush.cells(point, point, RADIUS);
+        let covered = cells.iter().filter(|&&cell| inside(cell)).count();
+
+        world.pour(&cells, 1.0);
+        assert_eq!(world.total_mass(), covered as f32, "pouring at {:?}", point);
+
+        world.pour(&cells, -2.0);
+        assert_eq!(world.total_mass(), 0.0, "draining at {:?}", point);
+
+        // Water poured onto the outermost cells must not reach past the grid
+        world.pour(&cells, 1.0);
+        world.tick();
+        world.pour(&cells, -2.0);
+        world.tick();
+    }
+}
+
+#[test]
+fn test_flood_fill_from_edges_and_corners() {
+    for &(x, y) in edge_points().iter().filter(|&&point| inside(point)) {
+        let mut world = World::new(WIDTH, HEIGHT);
+
+        world.flood_fill(x as usize, y as usize, Cell::Ground);
+
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                assert_eq!(world.cell(x, y), Cell::Ground);
+            }
+        }
+    }
+
+    // Starting outside of the world paints nothing
+    let mut world = World::new(WIDTH, HEIGHT);
+    world.flood_fill(WIDTH, HEIGHT, Cell::Ground);
+    assert_eq!(world.cell(WIDTH - 1, HEIGHT - 1), Cell::Air);
+}